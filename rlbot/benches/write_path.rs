@@ -0,0 +1,139 @@
+//! Sends a typical tick, one `PlayerInput` and a small `RenderGroup`, over a transport
+//! that throws everything away, and counts the heap allocations made while sending.
+//!
+//! Run with `cargo bench --bench write_path`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io::{self, Read, Write},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use rlbot::{
+    ClientPacket, RLBotConnection,
+    flat::{
+        Color, ControllerState, Line3D, PlayerInput, RenderAnchor, RenderGroup, RenderMessage,
+        RenderType, Vector3,
+    },
+    transport::Transport,
+};
+
+const WARMUP_TICKS: usize = 100;
+const TICKS: usize = 100_000;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Accepts every write and never has anything to read
+struct Sink;
+
+impl Read for Sink {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Sink {}
+
+fn anchor(x: f32) -> Box<RenderAnchor> {
+    Box::new(RenderAnchor {
+        world: Vector3 { x, y: 0., z: 100. },
+    })
+}
+
+/// Built before sending, so the bot's own allocations aren't counted
+fn tick_packets() -> [ClientPacket; 2] {
+    let render_messages = (0..4)
+        .map(|i| RenderMessage {
+            variety: RenderType::Line3D(Box::new(Line3D {
+                start: anchor(i as f32 * 100.),
+                end: anchor(i as f32 * 100. + 50.),
+            })),
+            color: Color {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255,
+            },
+        })
+        .collect();
+
+    [
+        PlayerInput {
+            player_index: 0,
+            controller_state: ControllerState {
+                throttle: 1.,
+                boost: true,
+                ..Default::default()
+            },
+        }
+        .into(),
+        RenderGroup {
+            render_messages,
+            id: 1,
+        }
+        .into(),
+    ]
+}
+
+fn main() {
+    let mut connection = RLBotConnection::from_transport(Sink);
+
+    // lets the reused buffers grow to their final size
+    for _ in 0..WARMUP_TICKS {
+        connection.send_packets(tick_packets()).unwrap();
+    }
+
+    let mut allocations = 0;
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..TICKS {
+        let packets = tick_packets();
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let started = Instant::now();
+        connection.send_packets(packets).unwrap();
+        elapsed += started.elapsed();
+        allocations += ALLOCATIONS.load(Ordering::Relaxed) - before;
+    }
+
+    println!(
+        "write_path: {:?} per tick, {} heap allocations per tick",
+        elapsed / TICKS as u32,
+        allocations as f64 / TICKS as f64,
+    );
+    assert_eq!(
+        allocations, 0,
+        "the write path shouldn't allocate once warmed up"
+    );
+}
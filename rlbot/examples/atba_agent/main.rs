@@ -1,12 +1,11 @@
 use std::{f32::consts::PI, sync::Arc};
 
 use rlbot::{
-    RLBotConnection,
-    agents::{Agent, run_agents},
-    flat::{
-        ControllableInfo, ControllerState, FieldInfo, GamePacket, MatchConfiguration, PlayerInput,
-    },
-    util::{PacketQueue, RLBotEnvironment},
+    agents::Agent,
+    context::{AgentContext, TickPacket},
+    flat::{ControllerState, FieldInfo, MatchConfiguration, PlayerInput},
+    runner::Runner,
+    util::PacketQueue,
 };
 
 #[allow(dead_code)]
@@ -20,36 +19,20 @@ struct AtbaAgent {
 }
 
 impl Agent for AtbaAgent {
-    fn new(
-        team: u32,
-        controllable_info: ControllableInfo,
-        match_config: Arc<MatchConfiguration>,
-        field_info: Arc<FieldInfo>,
-        _packet_queue: &mut PacketQueue,
-    ) -> Self {
-        let name = match_config
-            .player_configurations
-            .iter()
-            .find_map(|player| {
-                if player.spawn_id == controllable_info.spawn_id {
-                    Some(player.name.clone())
-                } else {
-                    None
-                }
-            })
-            .unwrap();
+    fn new(context: AgentContext, _packet_queue: &mut PacketQueue) -> Self {
+        let name = context.own_name().unwrap().to_owned();
 
         Self {
-            index: controllable_info.index,
-            spawn_id: controllable_info.spawn_id,
-            team,
+            index: context.controllable_info.index,
+            spawn_id: context.controllable_info.spawn_id,
+            team: context.team,
             name,
-            match_config,
-            field_info,
+            match_config: context.match_configuration,
+            field_info: context.field_info,
         }
     }
 
-    fn tick(&mut self, game_packet: &GamePacket, packet_queue: &mut PacketQueue) {
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
         let Some(ball) = game_packet.balls.first() else {
             // If theres no ball, theres nothing to chase, don't do anything
             return;
@@ -90,15 +73,15 @@ impl Agent for AtbaAgent {
 }
 
 fn main() {
-    let RLBotEnvironment {
-        server_addr,
-        agent_id,
-    } = RLBotEnvironment::from_env();
-    let agent_id = agent_id.unwrap_or_else(|| "rlbot/rust-example/atba_agent".into());
+    let runner = Runner::agents()
+        .fallback_agent_id("rlbot/rust-example/atba_agent")
+        .wants_ball_predictions(true)
+        .wants_comms(true);
+    let agent_id = runner.get_agent_id().unwrap_or_default().to_owned();
 
     println!("Connecting");
 
-    let rlbot_connection = RLBotConnection::new(&server_addr).expect("connection");
+    let rlbot_connection = runner.connect().expect("connection");
 
     println!("Running!");
 
@@ -108,7 +91,8 @@ fn main() {
     // all of the bots in a team.
 
     // Blocking.
-    run_agents::<AtbaAgent>(agent_id.clone(), true, true, rlbot_connection)
+    runner
+        .run_with_connection::<AtbaAgent>(rlbot_connection)
         .expect("run_agents crashed");
 
     println!("Agent(s) with agent_id `{agent_id}` exited nicely");
@@ -1,13 +1,11 @@
 use std::f32::consts::PI;
 
 use rlbot::{
-    RLBotConnection,
-    flat::{
-        ControllableTeamInfo, ControllerState, FieldInfo, GamePacket, MatchConfiguration,
-        PlayerInput,
-    },
-    hivemind::{Hivemind, run_hivemind},
-    util::{PacketQueue, RLBotEnvironment},
+    context::{HivemindContext, TickPacket},
+    flat::{ControllerState, FieldInfo, MatchConfiguration, PlayerInput},
+    hivemind::Hivemind,
+    runner::Runner,
+    util::PacketQueue,
 };
 
 #[allow(dead_code)]
@@ -21,30 +19,14 @@ struct AtbaHivemind {
 }
 
 impl Hivemind for AtbaHivemind {
-    fn new(
-        controllable_team_info: ControllableTeamInfo,
-        match_config: MatchConfiguration,
-        field_info: FieldInfo,
-        _packet_queue: &mut PacketQueue,
-    ) -> Self {
-        let names = match_config
-            .player_configurations
-            .iter()
-            .filter_map(|player| {
-                controllable_team_info
-                    .controllables
-                    .iter()
-                    .find_map(|controllable| {
-                        if controllable.spawn_id == player.spawn_id {
-                            Some(player.name.clone())
-                        } else {
-                            None
-                        }
-                    })
-            })
+    fn new(context: HivemindContext, _packet_queue: &mut PacketQueue) -> Self {
+        let names = context
+            .own_player_configurations()
+            .map(|player| player.name.clone())
             .collect();
 
-        let (indices, spawn_ids) = controllable_team_info
+        let (indices, spawn_ids) = context
+            .controllable_team_info
             .controllables
             .iter()
             .map(|controllable| (controllable.index, controllable.spawn_id))
@@ -53,14 +35,14 @@ impl Hivemind for AtbaHivemind {
         Self {
             indices,
             spawn_ids,
-            team: controllable_team_info.team,
+            team: context.controllable_team_info.team,
             names,
-            match_config,
-            field_info,
+            match_config: context.match_configuration,
+            field_info: context.field_info,
         }
     }
 
-    fn tick(&mut self, game_packet: GamePacket, packet_queue: &mut PacketQueue) {
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
         let Some(ball) = game_packet.balls.first() else {
             // If theres no ball, theres nothing to chase, don't do anything
             return;
@@ -103,15 +85,15 @@ impl Hivemind for AtbaHivemind {
 }
 
 fn main() {
-    let RLBotEnvironment {
-        server_addr,
-        agent_id,
-    } = RLBotEnvironment::from_env();
-    let agent_id = agent_id.unwrap_or_else(|| "rlbot/rust-example/atba_hivemind".into());
+    let runner = Runner::hivemind()
+        .fallback_agent_id("rlbot/rust-example/atba_hivemind")
+        .wants_ball_predictions(true)
+        .wants_comms(true);
+    let agent_id = runner.get_agent_id().unwrap_or_default().to_owned();
 
     println!("Connecting");
 
-    let rlbot_connection = RLBotConnection::new(&server_addr).expect("connection");
+    let rlbot_connection = runner.connect().expect("connection");
 
     println!("Running!");
 
@@ -121,7 +103,8 @@ fn main() {
     // all of the bots in a team.
 
     // Blocking.
-    run_hivemind::<AtbaHivemind>(agent_id.clone(), true, true, rlbot_connection)
+    runner
+        .run_with_connection::<AtbaHivemind>(rlbot_connection)
         .expect("run_hivemind crashed");
 
     println!("Hivemind with agent_id `{agent_id}` exited nicely");
@@ -1,7 +1,7 @@
 use std::f32::consts::PI;
 
 use rlbot::{
-    Packet, RLBotConnection,
+    ClientPacket, RLBotConnection, ServerPacket,
     flat::{ConnectionSettings, ControllerState, PlayerInput},
     util::RLBotEnvironment,
 };
@@ -31,7 +31,7 @@ fn main() {
     // Wait for ControllableTeamInfo to know which indices we control
     let controllable_team_info = loop {
         let packet = rlbot_connection.recv_packet().unwrap();
-        if let Packet::ControllableTeamInfo(x) = packet {
+        if let ServerPacket::ControllableTeamInfo(x) = packet {
             break x;
         }
 
@@ -48,10 +48,12 @@ fn main() {
         .first()
         .expect("controllables.len() = 1");
 
-    rlbot_connection.send_packet(Packet::InitComplete).unwrap();
+    rlbot_connection
+        .send_packet(ClientPacket::InitComplete)
+        .unwrap();
 
     loop {
-        let Packet::GamePacket(game_packet) = packets_to_process
+        let ServerPacket::GamePacket(game_packet) = packets_to_process
             .pop()
             .unwrap_or_else(|| rlbot_connection.recv_packet().unwrap())
         else {
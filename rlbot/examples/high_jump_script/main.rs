@@ -1,11 +1,9 @@
 use rlbot::{
-    RLBotConnection,
-    flat::{
-        DesiredCarState, DesiredGameState, DesiredPhysics, FieldInfo, GamePacket,
-        MatchConfiguration, MatchPhase, Vector3Partial,
-    },
-    scripts::{Script, run_script},
-    util::{PacketQueue, RLBotEnvironment},
+    context::{ScriptContext, TickPacket},
+    flat::{DesiredCarState, DesiredGameState, DesiredPhysics, MatchPhase, Vector3Partial},
+    runner::Runner,
+    scripts::Script,
+    util::PacketQueue,
 };
 
 #[allow(dead_code)]
@@ -16,32 +14,17 @@ struct MyScript {
 }
 
 impl Script for MyScript {
-    fn new(
-        agent_id: String,
-        match_config: MatchConfiguration,
-        _field_info: FieldInfo,
-        _packet_queue: &mut PacketQueue,
-    ) -> Self {
-        let name = match_config
-            .script_configurations
-            .iter()
-            .find_map(|script| {
-                if script.agent_id == agent_id {
-                    Some(script.name.clone())
-                } else {
-                    None
-                }
-            })
-            .unwrap();
+    fn new(context: ScriptContext, _packet_queue: &mut PacketQueue) -> Self {
+        let name = context.own_name().unwrap().to_owned();
 
         Self {
-            agent_id,
             name,
-            prev_jumps: vec![false; match_config.player_configurations.len()],
+            prev_jumps: vec![false; context.match_configuration.player_configurations.len()],
+            agent_id: context.agent_id,
         }
     }
 
-    fn tick(&mut self, game_packet: GamePacket, packet_queue: &mut PacketQueue) {
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
         if game_packet.match_info.match_phase != MatchPhase::Active {
             return;
         }
@@ -81,15 +64,16 @@ impl Script for MyScript {
 }
 
 fn main() {
-    let RLBotEnvironment {
-        server_addr,
-        agent_id,
-    } = RLBotEnvironment::from_env();
-    let agent_id = agent_id.unwrap_or_else(|| "rlbot/rust-example/high_jump_script".into());
-    let rlbot_connection = RLBotConnection::new(&server_addr).expect("connection");
+    let runner = Runner::script()
+        .fallback_agent_id("rlbot/rust-example/high_jump_script")
+        .wants_ball_predictions(true)
+        .wants_comms(true);
+    let agent_id = runner.get_agent_id().unwrap_or_default().to_owned();
+    let rlbot_connection = runner.connect().expect("connection");
 
     // Blocking.
-    run_script::<MyScript>(agent_id.clone(), true, true, rlbot_connection)
+    runner
+        .run_with_connection::<MyScript>(rlbot_connection)
         .expect("run_script crashed");
 
     println!("Script with agent_id `{agent_id}` exited nicely");
@@ -1,4 +1,65 @@
-use rlbot::{RLBotConnection, flat::ConnectionSettings, util::RLBotEnvironment};
+use std::{
+    env::args,
+    fs::File,
+    io::{self, BufWriter, Write},
+    net::TcpStream,
+};
+
+use rlbot::{
+    RLBotConnection,
+    flat::{
+        BallPrediction, ConnectionSettings, ControllableTeamInfo, FieldInfo, GamePacket, MatchComm,
+        MatchConfiguration,
+    },
+    handler::{PacketHandler, run_handler},
+    recording::Recorder,
+    util::{PacketQueue, RLBotEnvironment},
+};
+
+/// Prints every packet it gets
+struct PacketLogger;
+
+impl PacketHandler for PacketLogger {
+    fn on_game_packet(&mut self, game_packet: &GamePacket, _packet_queue: &mut PacketQueue) {
+        println!("{game_packet:?}");
+    }
+
+    fn on_field_info(&mut self, field_info: &FieldInfo, _packet_queue: &mut PacketQueue) {
+        println!("{field_info:?}");
+    }
+
+    fn on_match_configuration(
+        &mut self,
+        match_configuration: &MatchConfiguration,
+        _packet_queue: &mut PacketQueue,
+    ) {
+        println!("{match_configuration:?}");
+    }
+
+    fn on_controllable_team_info(
+        &mut self,
+        controllable_team_info: &ControllableTeamInfo,
+        _packet_queue: &mut PacketQueue,
+    ) {
+        println!("{controllable_team_info:?}");
+    }
+
+    fn on_match_comm(&mut self, match_comm: &MatchComm, _packet_queue: &mut PacketQueue) {
+        println!("{match_comm:?}");
+    }
+
+    fn on_ball_prediction(
+        &mut self,
+        ball_prediction: &BallPrediction,
+        _packet_queue: &mut PacketQueue,
+    ) {
+        println!("{ball_prediction:?}");
+    }
+
+    fn on_disconnect(&mut self) {
+        println!("Disconnected");
+    }
+}
 
 fn main() {
     let RLBotEnvironment {
@@ -7,7 +68,20 @@ fn main() {
     } = RLBotEnvironment::from_env();
     let agent_id = agent_id.unwrap_or_else(|| "rlbot/rust-packet-logger".into());
 
-    let mut rlbot_connection = RLBotConnection::new(&server_addr).expect("connection");
+    // Usage: ./packet_logger [session.rlbotrec]
+    // If a path is given, every frame is also recorded to that file
+    let output: Box<dyn Write> = match args().nth(1) {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).expect("create session file"),
+        )),
+        None => Box::new(io::sink()),
+    };
+
+    let stream = TcpStream::connect(&server_addr).expect("connection");
+    stream.set_nodelay(true).unwrap();
+
+    let mut rlbot_connection =
+        RLBotConnection::from_transport(Recorder::new(stream, output).expect("recorder"));
 
     println!("Connected");
 
@@ -20,8 +94,5 @@ fn main() {
         })
         .unwrap();
 
-    loop {
-        let packet = rlbot_connection.recv_packet().unwrap();
-        println!("{packet:?}");
-    }
+    run_handler(&mut PacketLogger, &mut rlbot_connection).expect("run_handler crashed");
 }
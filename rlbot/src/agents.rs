@@ -1,24 +1,86 @@
-use std::{mem, sync::Arc, thread};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    Packet, RLBotConnection, StartingInfo,
+    ClientPacket, RLBotConnection, RLBotError, ServerPacket, StartingInfo,
+    broadcast::{Broadcast, Work},
+    context::{AgentContext, TickPacket},
+    drain::{DrainEnd, Drained, drain, drain_ref},
     flat::*,
-    util::{PacketQueue, write_multiple_packets},
+    runner::{RunnerConfig, RunnerStats},
+    transport::Transport,
+    util::{InputChecker, PacketQueue, write_multiple_packets},
 };
 
 #[allow(unused_variables)]
 pub trait Agent {
-    // TODO: Maybe pass a struct?
-    fn new(
-        team: u32,
-        controllable_info: ControllableInfo,
-        match_configuration: Arc<MatchConfiguration>,
-        field_info: Arc<FieldInfo>,
-        packet_queue: &mut PacketQueue,
-    ) -> Self;
-    fn tick(&mut self, game_packet: &GamePacket, packet_queue: &mut PacketQueue);
+    fn new(context: AgentContext, packet_queue: &mut PacketQueue) -> Self;
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue);
     fn on_match_comm(&mut self, match_comm: &MatchComm, packet_queue: &mut PacketQueue) {}
     fn on_ball_prediction(&mut self, ball_prediction: &BallPrediction) {}
+    fn on_match_configuration(
+        &mut self,
+        match_configuration: &MatchConfiguration,
+        packet_queue: &mut PacketQueue,
+    ) {
+    }
+    fn on_field_info(&mut self, field_info: &FieldInfo, packet_queue: &mut PacketQueue) {}
+    fn on_controllable_team_info(
+        &mut self,
+        controllable_team_info: &ControllableTeamInfo,
+        packet_queue: &mut PacketQueue,
+    ) {
+    }
+    /// Called instead of creating a new agent when a new match starts
+    /// and `close_between_matches` is false. Rebuilds the agent with [`Self::new`] by default.
+    fn on_match_start(&mut self, context: AgentContext, packet_queue: &mut PacketQueue)
+    where
+        Self: Sized,
+    {
+        *self = Self::new(context, packet_queue);
+    }
+    /// Called when the match ends, before the next one starts or the runner exits
+    fn on_match_end(&mut self) {}
+}
+
+/// Like [`Agent`], but game packets and ball predictions are borrowed
+/// straight from the receive buffer instead of being converted to owned types.
+#[allow(unused_variables)]
+pub trait ZeroCopyAgent {
+    fn new(context: AgentContext, packet_queue: &mut PacketQueue) -> Self;
+    fn tick(&mut self, game_packet: GamePacketRef<'_>, packet_queue: &mut PacketQueue);
+    fn on_match_comm(&mut self, match_comm: &MatchComm, packet_queue: &mut PacketQueue) {}
+    fn on_ball_prediction(&mut self, ball_prediction: BallPredictionRef<'_>) {}
+    fn on_match_configuration(
+        &mut self,
+        match_configuration: &MatchConfiguration,
+        packet_queue: &mut PacketQueue,
+    ) {
+    }
+    fn on_field_info(&mut self, field_info: &FieldInfo, packet_queue: &mut PacketQueue) {}
+    fn on_controllable_team_info(
+        &mut self,
+        controllable_team_info: &ControllableTeamInfo,
+        packet_queue: &mut PacketQueue,
+    ) {
+    }
+    /// Called instead of creating a new agent when a new match starts
+    /// and `close_between_matches` is false. Rebuilds the agent with [`Self::new`] by default.
+    fn on_match_start(&mut self, context: AgentContext, packet_queue: &mut PacketQueue)
+    where
+        Self: Sized,
+    {
+        *self = Self::new(context, packet_queue);
+    }
+    /// Called when the match ends, before the next one starts or the runner exits
+    fn on_match_end(&mut self) {}
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -29,208 +91,920 @@ pub enum AgentError {
     PacketParseError(#[from] crate::RLBotError),
 }
 
-/// Run multiple agents with n agents per thread. They share a connection.
+/// Run multiple agents sharing a connection, each on its own thread.
 /// Ok(()) means a successful exit; one of the bots received a None packet.
 ///
+/// Shorthand for [`Runner::agents`](crate::runner::Runner::agents), which has more options,
+/// e.g. keeping the agents alive across matches or running them on fewer threads,
+/// see [`AgentScheduling`].
+///
 /// # Errors
 ///
-/// Returns an error if an agent panics or if there is an error with the connection.
+/// Returns an error if an agent panics, if there is an error with the connection
+/// or if an unexpected packet is received with [`UnexpectedPacketPolicy::Error`](crate::util::UnexpectedPacketPolicy::Error).
 ///
 /// # Panics
 ///
 /// Panics if a thread can't be spawned for each agent.
 pub fn run_agents<T: Agent>(
-    // TODO: Maybe pass a struct?
     agent_id: String,
     wants_ball_predictions: bool,
     wants_comms: bool,
-    mut connection: RLBotConnection,
+    connection: RLBotConnection<impl Transport>,
+) -> Result<(), AgentError> {
+    run_agents_with::<T>(
+        RunnerConfig::new(agent_id, wants_ball_predictions, wants_comms),
+        AgentOptions::default(),
+        connection,
+    )
+}
+
+pub(crate) fn run_agents_with<T: Agent>(
+    config: RunnerConfig,
+    options: AgentOptions,
+    mut connection: RLBotConnection<impl Transport>,
 ) -> Result<(), AgentError> {
-    connection.send_packet(ConnectionSettings {
-        agent_id,
-        wants_ball_predictions,
-        wants_comms,
-        close_between_matches: true,
-    })?;
-
-    let StartingInfo {
-        controllable_team_info,
-        match_configuration,
-        field_info,
-    } = connection.get_starting_info()?;
-
-    if controllable_team_info.controllables.is_empty() {
+    let close_between_matches = config.connection_settings.close_between_matches;
+    connection.send_packet(config.connection_settings.clone())?;
+
+    let starting_info = connection.get_starting_info()?;
+
+    if close_between_matches
+        && starting_info
+            .controllable_team_info
+            .controllables
+            .is_empty()
+    {
         // run no bots? no problem, done
         return Ok(());
     }
 
-    let match_configuration = Arc::new(match_configuration);
-    let field_info = Arc::new(field_info);
-
-    let num_threads = controllable_team_info.controllables.len();
-    let mut threads = Vec::with_capacity(num_threads);
-
-    let (outgoing_sender, outgoing_recver) = kanal::unbounded::<Vec<Packet>>();
-    for (i, controllable_info) in controllable_team_info.controllables.into_iter().enumerate() {
-        let (incoming_sender, incoming_recver) = kanal::unbounded::<Arc<Packet>>();
-        let match_configuration = match_configuration.clone();
-        let field_info = field_info.clone();
-
-        let outgoing_sender = outgoing_sender.clone();
-
-        threads.push((
-            incoming_sender,
-            thread::Builder::new()
-                .name(format!(
-                    "Agent thread {i} (index {})",
-                    controllable_info.index,
-                ))
-                .spawn(move || {
-                    run_agent::<T>(
-                        incoming_recver,
-                        controllable_team_info.team,
-                        controllable_info,
-                        match_configuration,
-                        field_info,
-                        outgoing_sender,
-                    );
+    let mut agents = RunningAgents::<T>::new(options, &config);
+    let init = agents.start(starting_info)?;
+    write_multiple_packets(
+        &mut connection,
+        init.into_iter().chain([ClientPacket::InitComplete]),
+    )?;
+
+    // Main loop, broadcast packet to all of the bots, then wait for all of the outgoing vecs
+    loop {
+        let Drained {
+            others,
+            frames,
+            end,
+        } = drain(&mut connection, &config)?;
+
+        for packet in others {
+            agents.handle(Arc::new(packet))?;
+        }
+
+        for frame in frames {
+            for match_comm in frame.comms {
+                agents.handle(Arc::new(ServerPacket::MatchComm(match_comm)))?;
+            }
+
+            if let Some(ball_prediction) = frame.ball_prediction {
+                agents.handle(Arc::new(ServerPacket::BallPrediction(ball_prediction)))?;
+            }
+
+            if let Some(game_packet) = frame.game_packet {
+                agents.handle(Arc::new(ServerPacket::GamePacket(game_packet)))?;
+
+                let to_send = agents.collect()?;
+                write_multiple_packets(&mut connection, to_send.into_iter())?;
+            }
+        }
+
+        match end {
+            Some(DrainEnd::MatchStart(first)) => {
+                let starting_info = connection.recv_starting_info(Some(first))?;
+                let init = agents.start(starting_info)?;
+                write_multiple_packets(
+                    &mut connection,
+                    init.into_iter().chain([ClientPacket::InitComplete]),
+                )?;
+            }
+            Some(DrainEnd::Stop) => {
+                agents.handle(Arc::new(ServerPacket::None))?;
+                break;
+            }
+            None => {}
+        }
+    }
+
+    agents.finish()
+}
+
+/// How [`Runner::agents`](crate::runner::Runner::agents) spreads the agents over threads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AgentScheduling {
+    /// Every agent gets its own thread
+    #[default]
+    ThreadPerAgent,
+    /// The agents are spread over at most this many threads,
+    /// agents sharing a thread are called one after another
+    WorkerPool(usize),
+    /// All agents are called one after another on the thread that runs them,
+    /// which avoids context switches when the agents are cheap
+    CallingThread,
+}
+
+/// How long the runner waits for agents to finish a tick before sending what's ready,
+/// see [`Runner::tick_deadline`](crate::runner::Runner::tick_deadline).
+/// Agents that missed it don't get new game packets until they caught up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TickDeadline {
+    /// Counted from when the game packet is passed to the agents
+    pub timeout: Duration,
+    /// Sends the last [`PlayerInput`]s of agents that missed the deadline again
+    pub repeat_last_input: bool,
+}
+
+/// What [`run_agents`] does when an agent panics,
+/// see [`Runner::on_agent_panic`](crate::runner::Runner::on_agent_panic).
+///
+/// Anything but [`Self::Propagate`] needs panics to unwind, with `panic = "abort"` the process
+/// still exits. Caught panics are counted in [`AgentStats::panics`] and [`RunnerStats::agent_panics`],
+/// the panic hook already reported them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AgentPanicPolicy {
+    /// Stops all agents and returns [`AgentError::AgentPanic`]
+    #[default]
+    Propagate,
+    /// Drops the agent until the next match, the other agents keep playing
+    Isolate,
+    /// Drops the agent and creates a new one with [`Agent::new`]
+    Restart,
+}
+
+/// Settings of [`run_agents`] that don't apply to hiveminds and scripts
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AgentOptions {
+    pub scheduling: AgentScheduling,
+    pub tick_deadline: Option<TickDeadline>,
+    pub on_panic: AgentPanicPolicy,
+}
+
+/// Counters kept by the runner for each agent, see [`AgentContext::stats`]
+#[derive(Debug, Default)]
+pub struct AgentStats {
+    ticks: AtomicU64,
+    missed_deadlines: AtomicU64,
+    panics: AtomicU64,
+    total_tick_nanos: AtomicU64,
+    last_tick_nanos: AtomicU64,
+    max_tick_nanos: AtomicU64,
+}
+
+impl AgentStats {
+    /// How many game packets the agent ticked on
+    pub fn ticks(&self) -> u64 {
+        self.ticks.load(Ordering::Relaxed)
+    }
+
+    /// How many times the runner stopped waiting for the agent, see [`TickDeadline`].
+    /// Agents that share a thread miss deadlines together.
+    pub fn missed_deadlines(&self) -> u64 {
+        self.missed_deadlines.load(Ordering::Relaxed)
+    }
+
+    /// How many times the agent panicked, see [`AgentPanicPolicy`]
+    pub fn panics(&self) -> u64 {
+        self.panics.load(Ordering::Relaxed)
+    }
+
+    pub fn last_tick_duration(&self) -> Duration {
+        Duration::from_nanos(self.last_tick_nanos.load(Ordering::Relaxed))
+    }
+
+    pub fn max_tick_duration(&self) -> Duration {
+        Duration::from_nanos(self.max_tick_nanos.load(Ordering::Relaxed))
+    }
+
+    pub fn mean_tick_duration(&self) -> Duration {
+        let total = self.total_tick_nanos.load(Ordering::Relaxed);
+        Duration::from_nanos(total / self.ticks().max(1))
+    }
+
+    fn record_tick(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+        self.total_tick_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.last_tick_nanos.store(nanos, Ordering::Relaxed);
+        self.max_tick_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    fn record_missed_deadline(&self) {
+        self.missed_deadlines.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Runs `f` and catches a panic in it, unless the policy is to propagate it.
+/// Anything queued before the panic is dropped.
+fn catch_agent_panic<R>(
+    on_panic: AgentPanicPolicy,
+    context: &AgentContext,
+    runner_stats: &RunnerStats,
+    packet_queue: &mut PacketQueue,
+    f: impl FnOnce(&mut PacketQueue) -> R,
+) -> Option<R> {
+    if on_panic == AgentPanicPolicy::Propagate {
+        return Some(f(packet_queue));
+    }
+
+    let queued = packet_queue.len();
+    match panic::catch_unwind(AssertUnwindSafe(|| f(packet_queue))) {
+        Ok(res) => Some(res),
+        Err(_) => {
+            packet_queue.truncate(queued);
+            context.stats.panics.fetch_add(1, Ordering::Relaxed);
+            runner_stats.record_agent_panic();
+
+            None
+        }
+    }
+}
+
+/// The agents of the current match, on worker threads or on the calling thread
+pub(crate) enum RunningAgents<T> {
+    Workers {
+        workers: Vec<AgentThread>,
+        broadcast: Arc<Broadcast<AgentMessage>>,
+        options: AgentOptions,
+        input_checker: InputChecker,
+        runner_stats: Arc<RunnerStats>,
+    },
+    Local(AgentGroup<T>),
+}
+
+impl<T: Agent> RunningAgents<T> {
+    pub fn new(options: AgentOptions, config: &RunnerConfig) -> Self {
+        let input_checker = InputChecker::new(config);
+        let runner_stats = config.runner_stats.clone();
+
+        if options.scheduling == AgentScheduling::CallingThread {
+            return Self::Local(AgentGroup::new(&options, input_checker, runner_stats));
+        }
+
+        Self::Workers {
+            workers: Vec::new(),
+            broadcast: Arc::default(),
+            options,
+            input_checker,
+            runner_stats,
+        }
+    }
+
+    /// Starts a match by reusing the running agents, creating new ones or stopping
+    /// the ones that aren't needed anymore, then returns what they queued
+    pub fn start(&mut self, starting_info: StartingInfo) -> Result<Vec<ClientPacket>, AgentError> {
+        let contexts = AgentContext::from_starting_info(starting_info);
+
+        let (workers, broadcast, options, input_checker, runner_stats) = match self {
+            Self::Workers {
+                workers,
+                broadcast,
+                options,
+                input_checker,
+                runner_stats,
+            } => (
+                workers,
+                &*broadcast,
+                &*options,
+                &*input_checker,
+                &*runner_stats,
+            ),
+            Self::Local(group) => {
+                catch_local_panic(|| group.start(contexts))?;
+                return Ok(group.take_queued()?);
+            }
+        };
+
+        let num_workers = match options.scheduling {
+            AgentScheduling::WorkerPool(size) => size.clamp(1, contexts.len().max(1)),
+            _ => contexts.len(),
+        };
+        let mut groups: Vec<Vec<AgentContext>> = (0..num_workers).map(|_| Vec::new()).collect();
+        for (i, context) in contexts.into_iter().enumerate() {
+            groups[i % num_workers].push(context);
+        }
+
+        let num_reused = num_workers.min(workers.len());
+        for (i, worker) in workers.drain(num_reused..).enumerate() {
+            broadcast.send(
+                num_reused + i,
+                AgentMessage::Packet(Arc::new(ServerPacket::None)),
+            );
+            worker.handle.join().map_err(|_| AgentError::AgentPanic)?;
+        }
+        broadcast.truncate_workers(num_reused);
+
+        for (i, contexts) in groups.into_iter().enumerate() {
+            if i >= workers.len() {
+                workers.push(AgentThread::spawn::<T>(
+                    broadcast,
+                    options.clone(),
+                    input_checker.clone(),
+                    runner_stats.clone(),
+                ));
+            }
+
+            workers[i].stats = contexts
+                .iter()
+                .map(|context| context.stats.clone())
+                .collect();
+            workers[i].last_inputs.clear();
+            broadcast.send(i, AgentMessage::MatchStart(contexts));
+        }
+        broadcast.start_round();
+
+        Self::collect_answers(workers, broadcast, None)
+    }
+
+    /// Passes a packet to every agent. Agents that are still busy with an earlier game packet
+    /// only get the latest game packet and ball prediction once they're done.
+    pub fn handle(&mut self, packet: Arc<ServerPacket>) -> Result<(), AgentError> {
+        match self {
+            Self::Workers {
+                broadcast,
+                runner_stats,
+                ..
+            } => match *packet {
+                ServerPacket::GamePacket(_) => broadcast.publish_game_packet(packet),
+                ServerPacket::BallPrediction(_) => broadcast.publish_ball_prediction(packet),
+                ServerPacket::MatchComm(_) => {
+                    let dropped = broadcast.send_all_lossy(AgentMessage::Packet(packet));
+                    runner_stats.record_dropped_comms(dropped);
+                }
+                _ => broadcast.send_all(AgentMessage::Packet(packet)),
+            },
+            Self::Local(group) => catch_local_panic(|| group.dispatch(&packet))?,
+        }
+
+        Ok(())
+    }
+
+    /// Returns what the agents queued, waiting until the [`TickDeadline`] if there is one.
+    /// Only call this after handling a game packet.
+    pub fn collect(&mut self) -> Result<Vec<ClientPacket>, AgentError> {
+        match self {
+            Self::Workers {
+                workers,
+                broadcast,
+                options,
+                ..
+            } => Self::collect_answers(workers, broadcast, options.tick_deadline),
+            Self::Local(group) => Ok(group.take_queued()?),
+        }
+    }
+
+    fn collect_answers(
+        workers: &mut [AgentThread],
+        broadcast: &Broadcast<AgentMessage>,
+        tick_deadline: Option<TickDeadline>,
+    ) -> Result<Vec<ClientPacket>, AgentError> {
+        let deadline = tick_deadline.map(|tick_deadline| Instant::now() + tick_deadline.timeout);
+
+        let mut to_send = Vec::new();
+        for (worker, answers) in workers.iter_mut().zip(broadcast.collect(deadline)) {
+            if answers.stopped {
+                return Err(AgentError::AgentPanic);
+            }
+
+            let packets = answers.packets?;
+            let inputs: Vec<_> = packets
+                .iter()
+                .filter_map(|packet| match packet {
+                    ClientPacket::PlayerInput(x) => Some(x.clone()),
+                    _ => None,
                 })
-                .unwrap(),
-        ));
+                .collect();
+            let sent_inputs = !inputs.is_empty();
+            if sent_inputs {
+                worker.last_inputs = inputs;
+            }
+            to_send.extend(packets);
+
+            if answers.caught_up {
+                continue;
+            }
+
+            for stats in &worker.stats {
+                stats.record_missed_deadline();
+            }
+
+            // a late answer to an earlier game packet already has inputs, don't send them twice
+            if !sent_inputs
+                && tick_deadline.is_some_and(|tick_deadline| tick_deadline.repeat_last_input)
+            {
+                to_send.extend(
+                    worker
+                        .last_inputs
+                        .iter()
+                        .cloned()
+                        .map(ClientPacket::PlayerInput),
+                );
+            }
+        }
+
+        Ok(to_send)
     }
-    // drop never-again-used copy of outgoing_sender
-    // DO NOT REMOVE, otherwise outgoing_recver.recv() will never error
-    // which we rely on for clean exiting
-    drop(outgoing_sender);
 
-    let mut to_send: Vec<Vec<Packet>> = vec![Vec::new(); num_threads];
-    for reserved_packet_spot in &mut to_send {
-        if let Ok(messages) = outgoing_recver.recv() {
-            *reserved_packet_spot = messages;
-        } else {
-            return Err(AgentError::AgentPanic);
+    /// Waits for the workers to stop, after handling [`ServerPacket::None`]
+    pub fn finish(mut self) -> Result<(), AgentError> {
+        if let Self::Workers { workers, .. } = &mut self {
+            for worker in workers.drain(..) {
+                worker.handle.join().map_err(|_| AgentError::AgentPanic)?;
+            }
         }
+
+        Ok(())
     }
+}
 
-    write_multiple_packets(
-        &mut connection,
-        to_send
-            .iter_mut()
-            .flat_map(mem::take)
-            .chain([Packet::InitComplete]),
-    )?;
+impl<T> Drop for RunningAgents<T> {
+    /// Stops the workers that are still running, e.g. after an error
+    fn drop(&mut self) {
+        if let Self::Workers {
+            workers, broadcast, ..
+        } = self
+        {
+            broadcast.close();
 
-    // Main loop, broadcast packet to all of the bots, then wait for all of the outgoing vecs
-    let mut ball_prediction = None;
-    let mut game_packet = None;
-    'main_loop: loop {
-        connection.set_nonblocking(true)?;
-        while let Ok(packet) = connection.recv_packet() {
-            let packet = Arc::new(packet);
-
-            match &*packet {
-                Packet::None => {
-                    for (incoming_sender, _) in &threads {
-                        if incoming_sender.send(packet.clone()).is_err() {
-                            return Err(AgentError::AgentPanic);
-                        }
-                    }
-
-                    break 'main_loop;
+            for worker in workers.drain(..) {
+                // panics were already turned into errors or printed
+                let _ = worker.handle.join();
+            }
+        }
+    }
+}
+
+/// Runs `f` on the calling thread, turning a panic that [`AgentPanicPolicy::Propagate`]
+/// let through into [`AgentError::AgentPanic`], like for agents on worker threads
+fn catch_local_panic<R>(f: impl FnOnce() -> R) -> Result<R, AgentError> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|_| AgentError::AgentPanic)
+}
+
+/// Agents that run on the same thread
+pub(crate) struct AgentGroup<T> {
+    /// `None` for agents that panicked and weren't restarted
+    agents: Vec<Option<T>>,
+    contexts: Vec<AgentContext>,
+    /// One per agent, so the [`InputPolicy`] can check them separately
+    queues: Vec<PacketQueue>,
+    on_panic: AgentPanicPolicy,
+    input_checker: InputChecker,
+    runner_stats: Arc<RunnerStats>,
+    /// Kept for [`TickPacket`], shared with the other workers instead of cloned
+    previous_game_packet: Option<Arc<ServerPacket>>,
+    ball_prediction: Option<Arc<ServerPacket>>,
+}
+
+impl<T: Agent> AgentGroup<T> {
+    fn new(
+        options: &AgentOptions,
+        input_checker: InputChecker,
+        runner_stats: Arc<RunnerStats>,
+    ) -> Self {
+        Self {
+            agents: Vec::new(),
+            contexts: Vec::new(),
+            queues: Vec::new(),
+            on_panic: options.on_panic,
+            input_checker,
+            runner_stats,
+            previous_game_packet: None,
+            ball_prediction: None,
+        }
+    }
+
+    /// Like [`start_agents`], but agents that panicked earlier are created again
+    fn start(&mut self, contexts: Vec<AgentContext>) {
+        for i in 0..self.agents.len() {
+            self.call(i, |agent, _| agent.on_match_end());
+        }
+
+        self.previous_game_packet = None;
+        self.ball_prediction = None;
+
+        self.agents.truncate(contexts.len());
+        self.agents.resize_with(contexts.len(), || None);
+        self.queues
+            .resize_with(contexts.len(), PacketQueue::default);
+        self.contexts = contexts;
+
+        for i in 0..self.agents.len() {
+            if self.agents[i].is_some() {
+                let context = self.contexts[i].clone();
+                self.call(i, |agent, packet_queue| {
+                    agent.on_match_start(context, packet_queue);
+                });
+            } else {
+                self.create(i);
+            }
+        }
+    }
+
+    /// Calls the callback matching `packet` on every agent
+    fn dispatch(&mut self, packet: &Arc<ServerPacket>) {
+        let previous_game_packet = match &**packet {
+            ServerPacket::GamePacket(_) => self.previous_game_packet.replace(packet.clone()),
+            ServerPacket::BallPrediction(_) => {
+                self.ball_prediction = Some(packet.clone());
+                None
+            }
+            _ => None,
+        };
+        let ball_prediction = self.ball_prediction.clone();
+
+        for i in 0..self.agents.len() {
+            match &**packet {
+                ServerPacket::None => self.call(i, |agent, _| agent.on_match_end()),
+                ServerPacket::GamePacket(x) => {
+                    let tick = TickPacket::new(
+                        x,
+                        previous_game_packet.as_deref().and_then(as_game_packet),
+                        ball_prediction.as_deref().and_then(as_ball_prediction),
+                    );
+
+                    let started = Instant::now();
+                    self.call(i, |agent, packet_queue| agent.tick(tick, packet_queue));
+                    self.contexts[i].stats.record_tick(started.elapsed());
+                }
+                ServerPacket::MatchComm(x) => {
+                    self.call(i, |agent, packet_queue| {
+                        agent.on_match_comm(x, packet_queue)
+                    });
                 }
-                Packet::MatchComm(_) => {
-                    for (incoming_sender, _) in &threads {
-                        if incoming_sender.send(packet.clone()).is_err() {
-                            return Err(AgentError::AgentPanic);
-                        }
-                    }
+                ServerPacket::BallPrediction(x) => {
+                    self.call(i, |agent, _| agent.on_ball_prediction(x))
                 }
-                Packet::BallPrediction(_) => ball_prediction = Some(packet),
-                Packet::GamePacket(_) => game_packet = Some(packet),
-                _ => panic!("Unexpected packet: {packet:?}"),
+                ServerPacket::MatchConfiguration(x) => self.call(i, |agent, packet_queue| {
+                    agent.on_match_configuration(x, packet_queue);
+                }),
+                ServerPacket::FieldInfo(x) => {
+                    self.call(i, |agent, packet_queue| {
+                        agent.on_field_info(x, packet_queue)
+                    });
+                }
+                ServerPacket::ControllableTeamInfo(x) => self.call(i, |agent, packet_queue| {
+                    agent.on_controllable_team_info(x, packet_queue);
+                }),
             }
         }
-        connection.set_nonblocking(false)?;
+    }
 
-        if let Some(game_packet) = game_packet.take() {
-            if let Some(ball_prediction) = ball_prediction.take() {
-                for (incoming_sender, _) in &threads {
-                    if incoming_sender.send(ball_prediction.clone()).is_err() {
-                        return Err(AgentError::AgentPanic);
-                    }
-                }
+    /// See [`take_queued`]
+    fn take_queued(&mut self) -> Result<Vec<ClientPacket>, RLBotError> {
+        take_queued(&mut self.queues, &self.contexts, &mut self.input_checker)
+    }
+
+    /// Calls `f` on the `i`th agent if it's alive, see [`AgentPanicPolicy`]
+    fn call(&mut self, i: usize, f: impl FnOnce(&mut T, &mut PacketQueue)) {
+        let Some(agent) = &mut self.agents[i] else {
+            return;
+        };
+
+        let survived = catch_agent_panic(
+            self.on_panic,
+            &self.contexts[i],
+            &self.runner_stats,
+            &mut self.queues[i],
+            |packet_queue| f(agent, packet_queue),
+        )
+        .is_some();
+
+        if !survived {
+            self.agents[i] = None;
+
+            if self.on_panic == AgentPanicPolicy::Restart {
+                self.create(i);
             }
+        }
+    }
+
+    fn create(&mut self, i: usize) {
+        let context = &self.contexts[i];
+
+        self.agents[i] = catch_agent_panic(
+            self.on_panic,
+            context,
+            &self.runner_stats,
+            &mut self.queues[i],
+            |packet_queue| T::new(context.clone(), packet_queue),
+        );
+    }
+}
+
+fn as_game_packet(packet: &ServerPacket) -> Option<&GamePacket> {
+    match packet {
+        ServerPacket::GamePacket(x) => Some(x),
+        _ => None,
+    }
+}
+
+fn as_ball_prediction(packet: &ServerPacket) -> Option<&BallPrediction> {
+    match packet {
+        ServerPacket::BallPrediction(x) => Some(x),
+        _ => None,
+    }
+}
 
-            for (incoming_sender, _) in &threads {
-                if incoming_sender.send(game_packet.clone()).is_err() {
-                    return Err(AgentError::AgentPanic);
+#[derive(Clone)]
+pub(crate) enum AgentMessage {
+    Packet(Arc<ServerPacket>),
+    /// See [`Agent::on_match_start`]
+    MatchStart(Vec<AgentContext>),
+}
+
+pub(crate) struct AgentThread {
+    handle: thread::JoinHandle<()>,
+    stats: Vec<Arc<AgentStats>>,
+    /// See [`TickDeadline::repeat_last_input`]
+    last_inputs: Vec<PlayerInput>,
+}
+
+impl AgentThread {
+    /// Starts a worker that waits for its [`AgentMessage::MatchStart`]
+    fn spawn<T: Agent>(
+        broadcast: &Arc<Broadcast<AgentMessage>>,
+        options: AgentOptions,
+        input_checker: InputChecker,
+        runner_stats: Arc<RunnerStats>,
+    ) -> Self {
+        let worker = broadcast.add_worker();
+        let broadcast = broadcast.clone();
+
+        let handle = thread::Builder::new()
+            .name(format!("Agent thread {worker}"))
+            .spawn(move || {
+                let _stopped = broadcast.stop_on_drop(worker);
+                let group = AgentGroup::<T>::new(&options, input_checker, runner_stats);
+                run_agent_thread(group, &broadcast, worker);
+            })
+            .unwrap();
+
+        Self {
+            handle,
+            stats: Vec::new(),
+            last_inputs: Vec::new(),
+        }
+    }
+}
+
+fn run_agent_thread<T: Agent>(
+    mut group: AgentGroup<T>,
+    broadcast: &Broadcast<AgentMessage>,
+    worker: usize,
+) {
+    loop {
+        match broadcast.next(worker) {
+            Work::Message(AgentMessage::MatchStart(contexts)) => group.start(contexts),
+            Work::Message(AgentMessage::Packet(packet)) => {
+                group.dispatch(&packet);
+
+                if matches!(*packet, ServerPacket::None) {
+                    break;
                 }
             }
+            Work::Frame {
+                ball_prediction,
+                game_packet,
+                round,
+            } => {
+                if let Some(ball_prediction) = ball_prediction {
+                    group.dispatch(&ball_prediction);
+                }
+
+                if let Some(game_packet) = game_packet {
+                    group.dispatch(&game_packet);
+                }
 
-            for reserved_packet_spot in &mut to_send {
-                if let Ok(messages) = outgoing_recver.recv() {
-                    *reserved_packet_spot = messages;
-                } else {
-                    break 'main_loop;
+                if let Some(round) = round {
+                    broadcast.answer(worker, round, group.take_queued());
                 }
             }
+            Work::Stop => {
+                group.dispatch(&Arc::new(ServerPacket::None));
+                break;
+            }
+        }
+    }
+}
 
-            write_multiple_packets(&mut connection, to_send.iter_mut().flat_map(mem::take))?;
+/// Starts a match for agents that all run on the same thread, each with its own queue,
+/// reusing the existing agents for the first contexts
+pub(crate) fn start_agents<T: Agent>(
+    agents: &mut Vec<T>,
+    queues: &mut Vec<PacketQueue>,
+    contexts: Vec<AgentContext>,
+) {
+    for agent in &mut *agents {
+        agent.on_match_end();
+    }
+
+    agents.truncate(contexts.len());
+    queues.resize_with(contexts.len(), PacketQueue::default);
+
+    for (i, (context, packet_queue)) in contexts.into_iter().zip(queues.iter_mut()).enumerate() {
+        match agents.get_mut(i) {
+            Some(agent) => agent.on_match_start(context, packet_queue),
+            None => agents.push(T::new(context, packet_queue)),
         }
     }
+}
 
-    for (_, handle) in threads {
-        handle.join().unwrap();
+/// [`ZeroCopyAgent`]s that all run on the calling thread,
+/// each with its own queue like in [`AgentGroup`]
+struct ZeroCopyAgents<T> {
+    agents: Vec<T>,
+    contexts: Vec<AgentContext>,
+    queues: Vec<PacketQueue>,
+    input_checker: InputChecker,
+}
+
+impl<T: ZeroCopyAgent> ZeroCopyAgents<T> {
+    fn new(input_checker: InputChecker) -> Self {
+        Self {
+            agents: Vec::new(),
+            contexts: Vec::new(),
+            queues: Vec::new(),
+            input_checker,
+        }
     }
 
-    Ok(())
+    /// Same as [`start_agents`], for [`ZeroCopyAgent`]s
+    fn start(&mut self, starting_info: StartingInfo) {
+        self.end();
+
+        let contexts = AgentContext::from_starting_info(starting_info);
+        self.agents.truncate(contexts.len());
+        self.queues
+            .resize_with(contexts.len(), PacketQueue::default);
+
+        for (i, context) in contexts.iter().enumerate() {
+            let packet_queue = &mut self.queues[i];
+            match self.agents.get_mut(i) {
+                Some(agent) => agent.on_match_start(context.clone(), packet_queue),
+                None => self.agents.push(T::new(context.clone(), packet_queue)),
+            }
+        }
+        self.contexts = contexts;
+    }
+
+    /// Calls `f` on every agent with its own queue
+    fn for_each(&mut self, mut f: impl FnMut(&mut T, &mut PacketQueue)) {
+        for (agent, packet_queue) in self.agents.iter_mut().zip(&mut self.queues) {
+            f(agent, packet_queue);
+        }
+    }
+
+    /// Ticks every agent, timing each one for its [`AgentStats`]
+    fn tick(&mut self, game_packet: GamePacketRef<'_>) {
+        let agents = self.agents.iter_mut().zip(&mut self.queues);
+        for ((agent, packet_queue), context) in agents.zip(&self.contexts) {
+            let started = Instant::now();
+            agent.tick(game_packet, packet_queue);
+            context.stats.record_tick(started.elapsed());
+        }
+    }
+
+    fn take_queued(&mut self) -> Result<Vec<ClientPacket>, RLBotError> {
+        take_queued(&mut self.queues, &self.contexts, &mut self.input_checker)
+    }
+
+    fn end(&mut self) {
+        for agent in &mut self.agents {
+            agent.on_match_end();
+        }
+    }
 }
 
-fn run_agent<T: Agent>(
-    incoming_recver: kanal::Receiver<Arc<Packet>>,
-    team: u32,
-    controllable_info: ControllableInfo,
-    match_configuration: Arc<MatchConfiguration>,
-    field_info: Arc<FieldInfo>,
-    outgoing_sender: kanal::Sender<Vec<Packet>>,
-) {
-    let mut outgoing_queue_local = PacketQueue::default();
-    let mut bot = T::new(
-        team,
-        controllable_info,
-        match_configuration,
-        field_info,
-        &mut outgoing_queue_local,
-    );
-
-    outgoing_sender
-        .send(outgoing_queue_local.empty())
-        .expect("Couldn't send outgoing");
+/// Takes what the agents queued, after checking each agent only sent inputs for its own car
+fn take_queued(
+    queues: &mut [PacketQueue],
+    contexts: &[AgentContext],
+    input_checker: &mut InputChecker,
+) -> Result<Vec<ClientPacket>, RLBotError> {
+    let mut to_send = Vec::new();
+    for (queue, context) in queues.iter_mut().zip(contexts) {
+        let index = context.controllable_info.index;
+        to_send.extend(queue.empty_checked(input_checker, &[index])?);
+    }
+
+    Ok(to_send)
+}
 
+/// Run multiple [`ZeroCopyAgent`]s sharing a connection.
+/// Packets are borrowed from the connection's receive buffer,
+/// so all agents run on the calling thread.
+/// Ok(()) means a successful exit; the server sent a None packet.
+///
+/// # Errors
+///
+/// Returns an error if there is an error with the connection
+/// or if an unexpected packet is received with [`UnexpectedPacketPolicy::Error`](crate::util::UnexpectedPacketPolicy::Error).
+pub fn run_zero_copy_agents<T: ZeroCopyAgent>(
+    agent_id: String,
+    wants_ball_predictions: bool,
+    wants_comms: bool,
+    connection: RLBotConnection<impl Transport>,
+) -> Result<(), AgentError> {
+    run_zero_copy_agents_with::<T>(
+        RunnerConfig::new(agent_id, wants_ball_predictions, wants_comms),
+        connection,
+    )
+}
+
+pub(crate) fn run_zero_copy_agents_with<T: ZeroCopyAgent>(
+    config: RunnerConfig,
+    mut connection: RLBotConnection<impl Transport>,
+) -> Result<(), AgentError> {
+    let close_between_matches = config.connection_settings.close_between_matches;
+    connection.send_packet(config.connection_settings.clone())?;
+
+    let starting_info = connection.get_starting_info()?;
+
+    if close_between_matches
+        && starting_info
+            .controllable_team_info
+            .controllables
+            .is_empty()
+    {
+        // run no bots? no problem, done
+        return Ok(());
+    }
+
+    let mut agents = ZeroCopyAgents::<T>::new(InputChecker::new(&config));
+    agents.start(starting_info);
+    write_multiple_packets(
+        &mut connection,
+        agents
+            .take_queued()?
+            .into_iter()
+            .chain([ClientPacket::InitComplete]),
+    )?;
+
+    let mut to_send = Vec::new();
     loop {
-        let Ok(packet) = incoming_recver.recv() else {
-            panic!("channel recv failed")
-        };
+        let Drained {
+            others,
+            frames,
+            end,
+        } = drain_ref(&mut connection, &config)?;
+
+        for packet in others {
+            agents.for_each(|agent, packet_queue| match &packet {
+                ServerPacket::MatchConfiguration(x) => {
+                    agent.on_match_configuration(x, packet_queue);
+                }
+                ServerPacket::FieldInfo(x) => agent.on_field_info(x, packet_queue),
+                ServerPacket::ControllableTeamInfo(x) => {
+                    agent.on_controllable_team_info(x, packet_queue);
+                }
+                _ => {}
+            });
+        }
 
-        match &*packet {
-            Packet::None => break,
-            Packet::GamePacket(x) => bot.tick(x, &mut outgoing_queue_local),
-            Packet::MatchComm(x) => {
-                bot.on_match_comm(x, &mut outgoing_queue_local);
+        // the frames borrow the connection's buffer, so everything is sent at once afterwards
+        for frame in frames {
+            for match_comm in frame.comms {
+                agents.for_each(|agent, packet_queue| {
+                    agent.on_match_comm(&match_comm, packet_queue);
+                });
             }
-            Packet::BallPrediction(x) => {
-                bot.on_ball_prediction(x);
+
+            if let Some(ball_prediction) = frame.ball_prediction {
+                agents.for_each(|agent, _| agent.on_ball_prediction(ball_prediction));
+            }
+
+            if let Some(game_packet) = frame.game_packet {
+                agents.tick(game_packet);
+
+                to_send.extend(agents.take_queued()?);
             }
-            _ => unreachable!(), /* The rest of the packets are only client -> server */
         }
 
-        if matches!(*packet, Packet::GamePacket(_)) {
-            outgoing_sender
-                .send(outgoing_queue_local.empty())
-                .expect("Couldn't send outgoing");
+        write_multiple_packets(&mut connection, to_send.drain(..))?;
+
+        match end {
+            Some(DrainEnd::MatchStart(first)) => {
+                agents.start(connection.recv_starting_info(Some(first))?);
+                write_multiple_packets(
+                    &mut connection,
+                    agents
+                        .take_queued()?
+                        .into_iter()
+                        .chain([ClientPacket::InitComplete]),
+                )?;
+            }
+            Some(DrainEnd::Stop) => break,
+            None => {}
         }
     }
 
-    drop(incoming_recver);
-    drop(outgoing_sender);
+    agents.end();
+
+    Ok(())
 }
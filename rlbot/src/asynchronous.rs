@@ -0,0 +1,322 @@
+use std::{io, net::SocketAddr, ops::Range, str::FromStr, sync::Arc};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{
+    ClientPacket, Packet, RLBotError, ServerPacket, StartingInfo, StartingInfoCollector,
+    agents::{Agent, AgentError, AgentOptions, AgentScheduling, RunningAgents},
+    dispatch::{Bot, Dispatcher},
+    drain::{DrainEnd, Drained, drain_async},
+    framing::{FrameDecoder, FrameEncoder},
+    hivemind::{Hivemind, HivemindBot, HivemindError},
+    runner::RunnerConfig,
+    scripts::{Script, ScriptBot, ScriptError},
+    util::{InputChecker, PacketQueue},
+};
+
+/// Async counterpart of [`RLBotConnection`](crate::RLBotConnection), for use inside a tokio runtime.
+pub struct AsyncRLBotConnection {
+    stream: TcpStream,
+    encoder: FrameEncoder,
+    recv_buf: FrameDecoder,
+}
+
+impl AsyncRLBotConnection {
+    pub async fn new(addr: &str) -> Result<Self, RLBotError> {
+        let stream = TcpStream::connect(SocketAddr::from_str(addr)?).await?;
+
+        stream.set_nodelay(true)?;
+
+        Ok(Self {
+            stream,
+            encoder: FrameEncoder::default(),
+            recv_buf: FrameDecoder::default(),
+        })
+    }
+
+    pub async fn send_packet(&mut self, packet: impl Into<ClientPacket>) -> Result<(), RLBotError> {
+        self.send_packets([packet.into()].into_iter()).await
+    }
+
+    pub(crate) async fn send_packets(
+        &mut self,
+        packets: impl Iterator<Item = ClientPacket>,
+    ) -> Result<(), RLBotError> {
+        let to_write = self.encoder.encode(packets.map(Packet::from))?;
+
+        self.stream.write_all(to_write).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// See [`RLBotConnection::set_split_render_groups`](crate::RLBotConnection::set_split_render_groups)
+    pub fn set_split_render_groups(&mut self, extra_ids: Option<Range<i32>>) {
+        self.encoder.split_render_groups = extra_ids;
+    }
+
+    pub async fn recv_packet(&mut self) -> Result<ServerPacket, RLBotError> {
+        loop {
+            if let Some(packet) = self.recv_buf.next_packet()? {
+                return Ok(packet);
+            }
+
+            let read = self.stream.read(self.recv_buf.read_space()).await?;
+            if read == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+
+            self.recv_buf.advance(read);
+        }
+    }
+
+    /// Returns the next packet if it can be received without waiting.
+    /// Partially received packets are kept and completed by later calls.
+    pub fn try_recv_packet(&mut self) -> Result<Option<ServerPacket>, RLBotError> {
+        loop {
+            if let Some(packet) = self.recv_buf.next_packet()? {
+                return Ok(Some(packet));
+            }
+
+            match self.stream.try_read(self.recv_buf.read_space()) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(read) => self.recv_buf.advance(read),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub async fn get_starting_info(&mut self) -> Result<StartingInfo, RLBotError> {
+        self.recv_starting_info(None).await
+    }
+
+    /// Like [`Self::get_starting_info`], but starts with an already received packet
+    pub(crate) async fn recv_starting_info(
+        &mut self,
+        mut first: Option<ServerPacket>,
+    ) -> Result<StartingInfo, RLBotError> {
+        let mut collector = StartingInfoCollector::default();
+
+        loop {
+            let packet = match first.take() {
+                Some(packet) => packet,
+                None => self.recv_packet().await?,
+            };
+
+            if let Some(starting_info) = collector.add(packet) {
+                return Ok(starting_info);
+            }
+        }
+    }
+}
+
+/// Async version of [`agents::run_agents`](crate::agents::run_agents).
+/// All agents run on the calling task instead of getting their own thread.
+/// Only receiving and sending packets is async: the agents' callbacks are called directly,
+/// so they block the executor thread while they run.
+/// Use a multi-threaded runtime or the sync runner on its own thread for agents that take long to tick.
+/// Ok(()) means a successful exit; the server sent a None packet.
+///
+/// # Errors
+///
+/// Returns an error if there is an error with the connection
+/// or if an unexpected packet is received with [`UnexpectedPacketPolicy::Error`](crate::util::UnexpectedPacketPolicy::Error).
+pub async fn run_agents<T: Agent>(
+    agent_id: String,
+    wants_ball_predictions: bool,
+    wants_comms: bool,
+    connection: AsyncRLBotConnection,
+) -> Result<(), AgentError> {
+    run_agents_with::<T>(
+        RunnerConfig::new(agent_id, wants_ball_predictions, wants_comms),
+        AgentOptions::default(),
+        connection,
+    )
+    .await
+}
+
+pub(crate) async fn run_agents_with<T: Agent>(
+    config: RunnerConfig,
+    options: AgentOptions,
+    mut connection: AsyncRLBotConnection,
+) -> Result<(), AgentError> {
+    let close_between_matches = config.connection_settings.close_between_matches;
+    connection
+        .send_packet(config.connection_settings.clone())
+        .await?;
+
+    let starting_info = connection.get_starting_info().await?;
+
+    if close_between_matches
+        && starting_info
+            .controllable_team_info
+            .controllables
+            .is_empty()
+    {
+        // run no bots? no problem, done
+        return Ok(());
+    }
+
+    let options = AgentOptions {
+        scheduling: AgentScheduling::CallingThread,
+        ..options
+    };
+    let mut agents = RunningAgents::<T>::new(options, &config);
+    let init = agents.start(starting_info)?;
+    connection
+        .send_packets(init.into_iter().chain([ClientPacket::InitComplete]))
+        .await?;
+
+    loop {
+        let Drained {
+            others,
+            frames,
+            end,
+        } = drain_async(&mut connection, &config).await?;
+
+        for packet in others {
+            agents.handle(Arc::new(packet))?;
+        }
+
+        for frame in frames {
+            for match_comm in frame.comms {
+                agents.handle(Arc::new(ServerPacket::MatchComm(match_comm)))?;
+            }
+
+            if let Some(ball_prediction) = frame.ball_prediction {
+                agents.handle(Arc::new(ServerPacket::BallPrediction(ball_prediction)))?;
+            }
+
+            if let Some(game_packet) = frame.game_packet {
+                agents.handle(Arc::new(ServerPacket::GamePacket(game_packet)))?;
+
+                let to_send = agents.collect()?;
+                connection.send_packets(to_send.into_iter()).await?;
+            }
+        }
+
+        match end {
+            Some(DrainEnd::MatchStart(first)) => {
+                let starting_info = connection.recv_starting_info(Some(first)).await?;
+                let init = agents.start(starting_info)?;
+                connection
+                    .send_packets(init.into_iter().chain([ClientPacket::InitComplete]))
+                    .await?;
+            }
+            Some(DrainEnd::Stop) => {
+                agents.handle(Arc::new(ServerPacket::None))?;
+                break;
+            }
+            None => {}
+        }
+    }
+
+    agents.finish()
+}
+
+/// Async version of [`hivemind::run_hivemind`](crate::hivemind::run_hivemind).
+/// Like with [`run_agents`], the hivemind's callbacks block the executor thread while they run.
+///
+/// # Errors
+///
+/// Returns an error if there is an error with the connection
+/// or if an unexpected packet is received with [`UnexpectedPacketPolicy::Error`](crate::util::UnexpectedPacketPolicy::Error).
+pub async fn run_hivemind<T: Hivemind>(
+    agent_id: String,
+    wants_ball_predictions: bool,
+    wants_comms: bool,
+    connection: AsyncRLBotConnection,
+) -> Result<(), HivemindError> {
+    run_hivemind_with::<T>(
+        RunnerConfig::new(agent_id, wants_ball_predictions, wants_comms),
+        connection,
+    )
+    .await
+}
+
+pub(crate) async fn run_hivemind_with<T: Hivemind>(
+    config: RunnerConfig,
+    connection: AsyncRLBotConnection,
+) -> Result<(), HivemindError> {
+    Ok(run(&config, connection, HivemindBot::<T>::new).await?)
+}
+
+/// Async version of [`scripts::run_script`](crate::scripts::run_script).
+/// Like with [`run_agents`], the script's callbacks block the executor thread while they run.
+///
+/// # Errors
+///
+/// Returns an error if there is an error with the connection
+/// or if an unexpected packet is received with [`UnexpectedPacketPolicy::Error`](crate::util::UnexpectedPacketPolicy::Error).
+pub async fn run_script<T: Script>(
+    agent_id: String,
+    wants_ball_predictions: bool,
+    wants_comms: bool,
+    connection: AsyncRLBotConnection,
+) -> Result<(), ScriptError> {
+    run_script_with::<T>(
+        RunnerConfig::new(agent_id, wants_ball_predictions, wants_comms),
+        connection,
+    )
+    .await
+}
+
+pub(crate) async fn run_script_with<T: Script>(
+    config: RunnerConfig,
+    connection: AsyncRLBotConnection,
+) -> Result<(), ScriptError> {
+    let agent_id = config.connection_settings.agent_id.clone();
+    Ok(run(&config, connection, |starting_info, packet_queue| {
+        ScriptBot::<T>::new(agent_id, starting_info, packet_queue)
+    })
+    .await?)
+}
+
+/// Like [`dispatch::run`](crate::dispatch::run), but async
+async fn run<T: Bot>(
+    config: &RunnerConfig,
+    mut connection: AsyncRLBotConnection,
+    new: impl FnOnce(StartingInfo, &mut PacketQueue) -> T,
+) -> Result<(), RLBotError> {
+    connection
+        .send_packet(config.connection_settings.clone())
+        .await?;
+
+    let starting_info = connection.get_starting_info().await?;
+    let mut dispatcher = Dispatcher::new(starting_info, InputChecker::new(config), new);
+    connection.send_packets(dispatcher.init_complete()?).await?;
+
+    loop {
+        let Drained {
+            others,
+            frames,
+            end,
+        } = drain_async(&mut connection, config).await?;
+
+        dispatcher.handle_others(others);
+
+        for frame in frames {
+            if let Some(to_send) = dispatcher.handle_frame(frame)? {
+                connection.send_packets(to_send).await?;
+            }
+        }
+
+        match end {
+            Some(DrainEnd::MatchStart(first)) => {
+                let starting_info = connection.recv_starting_info(Some(first)).await?;
+                connection
+                    .send_packets(dispatcher.restart(starting_info)?)
+                    .await?;
+            }
+            Some(DrainEnd::Stop) => break,
+            None => {}
+        }
+    }
+
+    dispatcher.finish();
+
+    Ok(())
+}
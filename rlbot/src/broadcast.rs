@@ -0,0 +1,329 @@
+//! How [`run_agents`](crate::agents::run_agents) passes packets to its worker threads.
+//!
+//! Game packets and ball predictions are published once into a slot shared by all workers
+//! that only keeps the latest of each, so a stalled worker skips to the newest frame
+//! instead of piling them up. Everything else goes into a small inbox per worker,
+//! which is always emptied before the next frame is handed out. When a stalled worker's inbox
+//! is full, its oldest comms are dropped instead.
+//! Workers answer every round (a game packet or a match start) with what they queued,
+//! and the runner waits for all answers like a barrier with an optional deadline.
+
+use std::{
+    collections::VecDeque,
+    mem,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    time::Instant,
+};
+
+use crate::{ClientPacket, RLBotError, ServerPacket};
+
+/// How many messages a worker's inbox holds before lossy ones are dropped, see [`Broadcast::send_all_lossy`]
+const INBOX_CAPACITY: usize = 256;
+
+pub(crate) struct Broadcast<M> {
+    state: Mutex<State<M>>,
+    /// Wakes up workers when there's something new for them
+    published: Condvar,
+    /// Wakes up the runner when a worker answered or stopped
+    answered: Condvar,
+}
+
+struct State<M> {
+    /// Bumped for every game packet and match start, which workers have to answer
+    round: u64,
+    game_packet: Option<Arc<ServerPacket>>,
+    /// The round `game_packet` was published in
+    game_packet_round: u64,
+    ball_prediction: Option<Arc<ServerPacket>>,
+    /// Bumped for every ball prediction
+    ball_prediction_seq: u64,
+    workers: Vec<WorkerState<M>>,
+    /// Set when the runner stops, workers quit instead of waiting for more
+    closed: bool,
+}
+
+struct WorkerState<M> {
+    /// Messages and whether they may be dropped when the inbox is full
+    inbox: VecDeque<(M, bool)>,
+    /// The last round this worker answered
+    answered: u64,
+    /// The last ball prediction this worker got
+    ball_prediction_seen: u64,
+    /// What the worker answered with since the runner last collected
+    outbox: Result<Vec<ClientPacket>, RLBotError>,
+    stopped: bool,
+}
+
+/// What a worker should do next, see [`Broadcast::next`]
+pub(crate) enum Work<M> {
+    Message(M),
+    /// The newest frame the worker hasn't seen yet, in the order it should be handled.
+    /// Answer with [`Broadcast::answer`] if `round` is set.
+    Frame {
+        ball_prediction: Option<Arc<ServerPacket>>,
+        game_packet: Option<Arc<ServerPacket>>,
+        round: Option<u64>,
+    },
+    /// The runner stopped, see [`Broadcast::close`]
+    Stop,
+}
+
+/// A worker's answers, see [`Broadcast::collect`]
+pub(crate) struct Answers {
+    /// Whether the worker answered the latest round in time
+    pub caught_up: bool,
+    /// Whether the worker's thread exited
+    pub stopped: bool,
+    pub packets: Result<Vec<ClientPacket>, RLBotError>,
+}
+
+impl<M> Default for Broadcast<M> {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(State {
+                round: 0,
+                game_packet: None,
+                game_packet_round: 0,
+                ball_prediction: None,
+                ball_prediction_seq: 0,
+                workers: Vec::new(),
+                closed: false,
+            }),
+            published: Condvar::new(),
+            answered: Condvar::new(),
+        }
+    }
+}
+
+impl<M> Broadcast<M> {
+    fn lock(&self) -> MutexGuard<'_, State<M>> {
+        // agents are never called while holding the lock, so the state is fine even if poisoned
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Registers a new worker that only gets what's published from now on, returns its index
+    pub fn add_worker(&self) -> usize {
+        let mut state = self.lock();
+        let worker = WorkerState {
+            inbox: VecDeque::new(),
+            answered: state.round,
+            ball_prediction_seen: state.ball_prediction_seq,
+            outbox: Ok(Vec::new()),
+            stopped: false,
+        };
+        state.workers.push(worker);
+
+        state.workers.len() - 1
+    }
+
+    /// Forgets the workers from index `len` on, after their threads exited
+    pub fn truncate_workers(&self, len: usize) {
+        self.lock().workers.truncate(len);
+    }
+
+    pub fn send(&self, worker: usize, message: M) {
+        self.lock().workers[worker]
+            .inbox
+            .push_back((message, false));
+        self.published.notify_all();
+    }
+
+    pub fn send_all(&self, message: M)
+    where
+        M: Clone,
+    {
+        for worker in &mut self.lock().workers {
+            worker.inbox.push_back((message.clone(), false));
+        }
+        self.published.notify_all();
+    }
+
+    /// Like [`Self::send_all`], but makes room in full inboxes by dropping their oldest
+    /// lossy message. Returns how many messages were dropped.
+    pub fn send_all_lossy(&self, message: M) -> u64
+    where
+        M: Clone,
+    {
+        let mut dropped = 0;
+        for worker in &mut self.lock().workers {
+            if worker.inbox.len() >= INBOX_CAPACITY
+                && let Some(oldest) = worker.inbox.iter().position(|(_, lossy)| *lossy)
+            {
+                worker.inbox.remove(oldest);
+                dropped += 1;
+            }
+
+            worker.inbox.push_back((message.clone(), true));
+        }
+        self.published.notify_all();
+
+        dropped
+    }
+
+    /// Starts a round without a game packet, after sending every worker its match start.
+    /// Frames of the previous match aren't handed out anymore.
+    pub fn start_round(&self) {
+        let mut state = self.lock();
+        state.round += 1;
+        state.game_packet = None;
+        state.ball_prediction = None;
+        drop(state);
+
+        self.published.notify_all();
+    }
+
+    pub fn publish_game_packet(&self, game_packet: Arc<ServerPacket>) {
+        let mut state = self.lock();
+        state.round += 1;
+        state.game_packet_round = state.round;
+        state.game_packet = Some(game_packet);
+        drop(state);
+
+        self.published.notify_all();
+    }
+
+    pub fn publish_ball_prediction(&self, ball_prediction: Arc<ServerPacket>) {
+        let mut state = self.lock();
+        state.ball_prediction_seq += 1;
+        state.ball_prediction = Some(ball_prediction);
+        drop(state);
+
+        self.published.notify_all();
+    }
+
+    /// Makes every worker stop at its next call to [`Self::next`],
+    /// even if there are still messages for it
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.published.notify_all();
+    }
+
+    /// Blocks until there's something for `worker` to do
+    pub fn next(&self, worker: usize) -> Work<M> {
+        let mut state = self.lock();
+
+        loop {
+            let State {
+                round,
+                game_packet,
+                game_packet_round,
+                ball_prediction,
+                ball_prediction_seq,
+                workers,
+                closed,
+            } = &mut *state;
+            let this = &mut workers[worker];
+
+            if *closed {
+                return Work::Stop;
+            }
+
+            if let Some((message, _)) = this.inbox.pop_front() {
+                return Work::Message(message);
+            }
+
+            let new_ball_prediction = *ball_prediction_seq > this.ball_prediction_seen;
+            let new_game_packet = *game_packet_round > this.answered;
+            let new_round = *round > this.answered;
+            if new_ball_prediction || new_round {
+                this.ball_prediction_seen = *ball_prediction_seq;
+
+                return Work::Frame {
+                    ball_prediction: ball_prediction.clone().filter(|_| new_ball_prediction),
+                    game_packet: game_packet.clone().filter(|_| new_game_packet),
+                    round: new_round.then_some(*round),
+                };
+            }
+
+            state = self
+                .published
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    pub fn answer(
+        &self,
+        worker: usize,
+        round: u64,
+        packets: Result<Vec<ClientPacket>, RLBotError>,
+    ) {
+        let mut state = self.lock();
+        let this = &mut state.workers[worker];
+        this.answered = round;
+
+        match (&mut this.outbox, packets) {
+            (Ok(outbox), Ok(packets)) => outbox.extend(packets),
+            (outbox @ Ok(_), Err(e)) => *outbox = Err(e),
+            (Err(_), _) => {}
+        }
+        drop(state);
+
+        self.answered.notify_all();
+    }
+
+    /// Marks `worker` as stopped once the returned guard is dropped,
+    /// so the runner doesn't wait for threads that exited or panicked
+    pub fn stop_on_drop(&self, worker: usize) -> StopOnDrop<'_, M> {
+        StopOnDrop {
+            broadcast: self,
+            worker,
+        }
+    }
+
+    /// Waits until every worker answered the latest round or `deadline` passed,
+    /// then takes everything they answered with so far
+    pub fn collect(&self, deadline: Option<Instant>) -> Vec<Answers> {
+        let mut state = self.lock();
+
+        loop {
+            let round = state.round;
+            if state
+                .workers
+                .iter()
+                .all(|worker| worker.answered == round || worker.stopped)
+            {
+                break;
+            }
+
+            state = match deadline {
+                None => self
+                    .answered
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner),
+                Some(deadline) => {
+                    let Some(timeout) = deadline.checked_duration_since(Instant::now()) else {
+                        break;
+                    };
+                    self.answered
+                        .wait_timeout(state, timeout)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+            };
+        }
+
+        let round = state.round;
+        state
+            .workers
+            .iter_mut()
+            .map(|worker| Answers {
+                caught_up: worker.answered == round,
+                stopped: worker.stopped,
+                packets: mem::replace(&mut worker.outbox, Ok(Vec::new())),
+            })
+            .collect()
+    }
+}
+
+pub(crate) struct StopOnDrop<'a, M> {
+    broadcast: &'a Broadcast<M>,
+    worker: usize,
+}
+
+impl<M> Drop for StopOnDrop<'_, M> {
+    fn drop(&mut self) {
+        self.broadcast.lock().workers[self.worker].stopped = true;
+        self.broadcast.answered.notify_all();
+    }
+}
@@ -0,0 +1,111 @@
+//! Options for establishing a TCP connection to RLBotServer,
+//! e.g. waiting for a server that hasn't started listening yet.
+
+use std::{
+    io,
+    net::{SocketAddr, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::RLBotError;
+
+/// How [`RLBotConnection::connect`](crate::RLBotConnection::connect) connects to RLBotServer.
+/// The default makes a single attempt without any timeouts, like
+/// [`RLBotConnection::new`](crate::RLBotConnection::new).
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    /// Timeout of a single connection attempt
+    pub connect_timeout: Option<Duration>,
+    /// How many times to retry after the first attempt failed
+    pub retries: u32,
+    /// Time to wait before the first retry, doubled after every retry
+    pub initial_backoff: Duration,
+    /// Upper limit for the time between retries
+    pub max_backoff: Duration,
+    /// Time after which no more attempts are made, counted from the first attempt
+    pub deadline: Option<Duration>,
+    /// Timeout for blocking reads on the established stream
+    pub read_timeout: Option<Duration>,
+    /// Timeout for writes on the established stream
+    pub write_timeout: Option<Duration>,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: None,
+            retries: 0,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            deadline: None,
+            read_timeout: None,
+            write_timeout: None,
+        }
+    }
+}
+
+impl ConnectOptions {
+    /// Keeps retrying until `deadline` has passed, for bots that may be started before RLBotServer
+    #[must_use]
+    pub fn wait_for_server(deadline: Duration) -> Self {
+        Self {
+            connect_timeout: Some(Duration::from_secs(1)),
+            retries: u32::MAX,
+            deadline: Some(deadline),
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn connect(&self, addr: SocketAddr) -> Result<TcpStream, RLBotError> {
+        let deadline = self.deadline.map(|deadline| Instant::now() + deadline);
+        let mut backoff = self.initial_backoff;
+        let mut retries = 0;
+
+        let stream = loop {
+            let remaining =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            let timeout = match (self.connect_timeout, remaining) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+
+            let res = match timeout {
+                Some(timeout) if timeout.is_zero() => return Err(RLBotError::Timeout),
+                Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+                None => TcpStream::connect(addr),
+            };
+
+            let err = match res {
+                Ok(stream) => break stream,
+                Err(e) => e,
+            };
+
+            if retries == self.retries {
+                return Err(map_timeout(err));
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() + backoff >= deadline) {
+                return Err(RLBotError::Timeout);
+            }
+
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(self.max_backoff);
+            retries += 1;
+        };
+
+        stream.set_read_timeout(self.read_timeout)?;
+        stream.set_write_timeout(self.write_timeout)?;
+
+        Ok(stream)
+    }
+}
+
+/// Blocking reads and writes that ran into a timeout fail with
+/// `WouldBlock` on unix and `TimedOut` on windows
+pub(crate) fn map_timeout(e: io::Error) -> RLBotError {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => RLBotError::Timeout,
+        _ => RLBotError::Connection(e),
+    }
+}
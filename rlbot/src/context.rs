@@ -0,0 +1,236 @@
+//! What agents, hiveminds and scripts are created and ticked with,
+//! with accessors for the parts of the match configuration that concern them.
+
+use std::{ops::Deref, sync::Arc};
+
+use crate::{
+    StartingInfo,
+    agents::AgentStats,
+    flat::{
+        BallPrediction, ControllableInfo, ControllableTeamInfo, FieldInfo, GamePacket,
+        MatchConfiguration, PlayerConfiguration, ScriptConfiguration,
+    },
+};
+
+/// Passed to [`Agent::new`](crate::agents::Agent::new), one per controllable
+#[derive(Debug, Clone)]
+pub struct AgentContext {
+    pub team: u32,
+    pub controllable_info: ControllableInfo,
+    pub match_configuration: Arc<MatchConfiguration>,
+    pub field_info: Arc<FieldInfo>,
+    /// Kept up to date by the runner while the agent runs
+    pub stats: Arc<AgentStats>,
+}
+
+impl AgentContext {
+    /// Splits the starting info into one context per controllable
+    pub(crate) fn from_starting_info(starting_info: StartingInfo) -> Vec<Self> {
+        let StartingInfo {
+            controllable_team_info,
+            match_configuration,
+            field_info,
+        } = starting_info;
+
+        let match_configuration = Arc::new(match_configuration);
+        let field_info = Arc::new(field_info);
+
+        controllable_team_info
+            .controllables
+            .into_iter()
+            .map(|controllable_info| Self {
+                team: controllable_team_info.team,
+                controllable_info,
+                match_configuration: match_configuration.clone(),
+                field_info: field_info.clone(),
+                stats: Arc::default(),
+            })
+            .collect()
+    }
+
+    /// The configuration of the player this agent controls, found by its spawn id
+    #[must_use]
+    pub fn own_player_configuration(&self) -> Option<&PlayerConfiguration> {
+        find_player(&self.match_configuration, &self.controllable_info)
+    }
+
+    /// The name of the player this agent controls, as shown in game
+    #[must_use]
+    pub fn own_name(&self) -> Option<&str> {
+        self.own_player_configuration()
+            .map(|player| player.name.as_str())
+    }
+
+    /// All other players on this agent's team
+    pub fn teammates(&self) -> impl Iterator<Item = &PlayerConfiguration> {
+        self.match_configuration
+            .player_configurations
+            .iter()
+            .filter(|player| {
+                player.team == self.team && player.spawn_id != self.controllable_info.spawn_id
+            })
+    }
+
+    /// All players on the other team
+    pub fn opponents(&self) -> impl Iterator<Item = &PlayerConfiguration> {
+        self.match_configuration
+            .player_configurations
+            .iter()
+            .filter(|player| player.team != self.team)
+    }
+}
+
+/// Passed to [`Hivemind::new`](crate::hivemind::Hivemind::new)
+#[derive(Debug, Clone)]
+pub struct HivemindContext {
+    pub controllable_team_info: ControllableTeamInfo,
+    pub match_configuration: MatchConfiguration,
+    pub field_info: FieldInfo,
+}
+
+impl From<StartingInfo> for HivemindContext {
+    fn from(starting_info: StartingInfo) -> Self {
+        Self {
+            controllable_team_info: starting_info.controllable_team_info,
+            match_configuration: starting_info.match_configuration,
+            field_info: starting_info.field_info,
+        }
+    }
+}
+
+impl HivemindContext {
+    /// The configuration of one of the players this hivemind controls, found by its spawn id
+    #[must_use]
+    pub fn player_configuration(
+        &self,
+        controllable_info: &ControllableInfo,
+    ) -> Option<&PlayerConfiguration> {
+        find_player(&self.match_configuration, controllable_info)
+    }
+
+    /// The configurations of the players this hivemind controls, in the same order
+    /// as the controllables. Controllables without a configuration are skipped.
+    pub fn own_player_configurations(&self) -> impl Iterator<Item = &PlayerConfiguration> {
+        self.controllable_team_info
+            .controllables
+            .iter()
+            .filter_map(|controllable_info| self.player_configuration(controllable_info))
+    }
+
+    /// All players on this hivemind's team it doesn't control
+    pub fn teammates(&self) -> impl Iterator<Item = &PlayerConfiguration> {
+        self.match_configuration
+            .player_configurations
+            .iter()
+            .filter(|player| {
+                player.team == self.controllable_team_info.team
+                    && !self
+                        .controllable_team_info
+                        .controllables
+                        .iter()
+                        .any(|controllable| controllable.spawn_id == player.spawn_id)
+            })
+    }
+
+    /// All players on the other team
+    pub fn opponents(&self) -> impl Iterator<Item = &PlayerConfiguration> {
+        self.match_configuration
+            .player_configurations
+            .iter()
+            .filter(|player| player.team != self.controllable_team_info.team)
+    }
+}
+
+/// Passed to [`Script::new`](crate::scripts::Script::new)
+#[derive(Debug, Clone)]
+pub struct ScriptContext {
+    pub agent_id: String,
+    pub match_configuration: MatchConfiguration,
+    pub field_info: FieldInfo,
+}
+
+impl ScriptContext {
+    pub(crate) fn from_starting_info(agent_id: String, starting_info: StartingInfo) -> Self {
+        Self {
+            agent_id,
+            match_configuration: starting_info.match_configuration,
+            field_info: starting_info.field_info,
+        }
+    }
+
+    /// The configuration of this script, found by its agent id
+    #[must_use]
+    pub fn own_script_configuration(&self) -> Option<&ScriptConfiguration> {
+        self.match_configuration
+            .script_configurations
+            .iter()
+            .find(|script| script.agent_id == self.agent_id)
+    }
+
+    /// The name of this script, as set in its config
+    #[must_use]
+    pub fn own_name(&self) -> Option<&str> {
+        self.own_script_configuration()
+            .map(|script| script.name.as_str())
+    }
+}
+
+/// Passed to `tick` of agents, hiveminds and scripts. Derefs to the current [`GamePacket`],
+/// and also borrows what the runner kept from earlier in the match.
+#[derive(Debug, Clone, Copy)]
+pub struct TickPacket<'a> {
+    game_packet: &'a GamePacket,
+    previous_game_packet: Option<&'a GamePacket>,
+    ball_prediction: Option<&'a BallPrediction>,
+}
+
+impl<'a> TickPacket<'a> {
+    /// For calling `tick` outside of a runner, e.g. in tests
+    #[must_use]
+    pub fn new(
+        game_packet: &'a GamePacket,
+        previous_game_packet: Option<&'a GamePacket>,
+        ball_prediction: Option<&'a BallPrediction>,
+    ) -> Self {
+        Self {
+            game_packet,
+            previous_game_packet,
+            ball_prediction,
+        }
+    }
+
+    #[must_use]
+    pub fn game_packet(&self) -> &'a GamePacket {
+        self.game_packet
+    }
+
+    /// The game packet of the previous tick, `None` on the first tick of a match
+    #[must_use]
+    pub fn previous_game_packet(&self) -> Option<&'a GamePacket> {
+        self.previous_game_packet
+    }
+
+    /// The latest ball prediction of this match, which can be older than the game packet
+    #[must_use]
+    pub fn ball_prediction(&self) -> Option<&'a BallPrediction> {
+        self.ball_prediction
+    }
+}
+
+impl Deref for TickPacket<'_> {
+    type Target = GamePacket;
+
+    fn deref(&self) -> &GamePacket {
+        self.game_packet
+    }
+}
+
+fn find_player<'a>(
+    match_configuration: &'a MatchConfiguration,
+    controllable_info: &ControllableInfo,
+) -> Option<&'a PlayerConfiguration> {
+    match_configuration
+        .player_configurations
+        .iter()
+        .find(|player| player.spawn_id == controllable_info.spawn_id)
+}
@@ -0,0 +1,187 @@
+//! What the hivemind and script runners do between receiving and sending packets,
+//! shared by the sync and async runners so they only differ in how they use the connection.
+
+use std::vec;
+
+use crate::{
+    ClientPacket, RLBotConnection, RLBotError, ServerPacket, StartingInfo,
+    context::TickPacket,
+    drain::{DrainEnd, Drained, Frame, drain},
+    flat::{
+        BallPrediction, ControllableTeamInfo, FieldInfo, GamePacket, MatchComm, MatchConfiguration,
+    },
+    runner::RunnerConfig,
+    transport::Transport,
+    util::{InputChecker, LastPackets, PacketQueue, controlled_indices, write_multiple_packets},
+};
+
+/// The callbacks hiveminds and scripts have in common
+pub(crate) trait Bot {
+    /// Whether the inputs the bot queues are checked against the cars it controls
+    const CONTROLS_CARS: bool;
+
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue);
+    fn on_match_comm(&mut self, match_comm: &MatchComm, packet_queue: &mut PacketQueue);
+    fn on_ball_prediction(&mut self, ball_prediction: &BallPrediction);
+    fn on_match_configuration(
+        &mut self,
+        match_configuration: &MatchConfiguration,
+        packet_queue: &mut PacketQueue,
+    );
+    fn on_field_info(&mut self, field_info: &FieldInfo, packet_queue: &mut PacketQueue);
+    fn on_controllable_team_info(
+        &mut self,
+        controllable_team_info: &ControllableTeamInfo,
+        packet_queue: &mut PacketQueue,
+    );
+    /// Ends the current match and starts the one described by `starting_info`
+    fn restart(&mut self, starting_info: StartingInfo, packet_queue: &mut PacketQueue);
+    fn on_match_end(&mut self);
+}
+
+/// A [`Bot`] and everything its runner keeps between ticks
+pub(crate) struct Dispatcher<T> {
+    bot: T,
+    packet_queue: PacketQueue,
+    last_packets: LastPackets,
+    input_checker: InputChecker,
+    controlled: Vec<u32>,
+}
+
+impl<T: Bot> Dispatcher<T> {
+    /// Creates the bot for the first match with `new`
+    pub fn new(
+        starting_info: StartingInfo,
+        input_checker: InputChecker,
+        new: impl FnOnce(StartingInfo, &mut PacketQueue) -> T,
+    ) -> Self {
+        let controlled = Self::controlled(&starting_info);
+        let mut packet_queue = PacketQueue::default();
+        let bot = new(starting_info, &mut packet_queue);
+
+        Self {
+            bot,
+            packet_queue,
+            last_packets: LastPackets::default(),
+            input_checker,
+            controlled,
+        }
+    }
+
+    fn controlled(starting_info: &StartingInfo) -> Vec<u32> {
+        if T::CONTROLS_CARS {
+            controlled_indices(&starting_info.controllable_team_info)
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// What the bot queued while starting the match, followed by [`ClientPacket::InitComplete`]
+    pub fn init_complete(&mut self) -> Result<vec::Drain<'_, ClientPacket>, RLBotError> {
+        self.packet_queue.push(ClientPacket::InitComplete);
+        self.packet_queue
+            .empty_checked(&mut self.input_checker, &self.controlled)
+    }
+
+    /// Passes on the packets that weren't part of a frame
+    pub fn handle_others(&mut self, others: Vec<ServerPacket>) {
+        for packet in others {
+            match packet {
+                ServerPacket::MatchConfiguration(x) => {
+                    self.bot.on_match_configuration(&x, &mut self.packet_queue);
+                }
+                ServerPacket::FieldInfo(x) => self.bot.on_field_info(&x, &mut self.packet_queue),
+                ServerPacket::ControllableTeamInfo(x) => {
+                    self.bot
+                        .on_controllable_team_info(&x, &mut self.packet_queue);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Passes `frame` on, then returns what the bot queued if it ticked
+    pub fn handle_frame(
+        &mut self,
+        frame: Frame<BallPrediction, GamePacket>,
+    ) -> Result<Option<vec::Drain<'_, ClientPacket>>, RLBotError> {
+        for match_comm in &frame.comms {
+            self.bot.on_match_comm(match_comm, &mut self.packet_queue);
+        }
+
+        if let Some(ball_prediction) = frame.ball_prediction {
+            self.bot
+                .on_ball_prediction(self.last_packets.ball_prediction(ball_prediction));
+        }
+
+        let Some(game_packet) = frame.game_packet else {
+            return Ok(None);
+        };
+        self.last_packets.tick(game_packet, |tick| {
+            self.bot.tick(tick, &mut self.packet_queue)
+        });
+
+        self.packet_queue
+            .empty_checked(&mut self.input_checker, &self.controlled)
+            .map(Some)
+    }
+
+    /// Ends the match and starts the next one, then returns what to send like [`Self::init_complete`]
+    pub fn restart(
+        &mut self,
+        starting_info: StartingInfo,
+    ) -> Result<vec::Drain<'_, ClientPacket>, RLBotError> {
+        self.controlled = Self::controlled(&starting_info);
+        self.bot.restart(starting_info, &mut self.packet_queue);
+        self.last_packets.clear();
+
+        self.init_complete()
+    }
+
+    /// Ends the last match
+    pub fn finish(mut self) {
+        self.bot.on_match_end();
+    }
+}
+
+/// Runs the bot created by `new` until the server sends [`ServerPacket::None`]
+pub(crate) fn run<T: Bot>(
+    config: &RunnerConfig,
+    mut connection: RLBotConnection<impl Transport>,
+    new: impl FnOnce(StartingInfo, &mut PacketQueue) -> T,
+) -> Result<(), RLBotError> {
+    connection.send_packet(config.connection_settings.clone())?;
+
+    let starting_info = connection.get_starting_info()?;
+    let mut dispatcher = Dispatcher::new(starting_info, InputChecker::new(config), new);
+    write_multiple_packets(&mut connection, dispatcher.init_complete()?)?;
+
+    loop {
+        let Drained {
+            others,
+            frames,
+            end,
+        } = drain(&mut connection, config)?;
+
+        dispatcher.handle_others(others);
+
+        for frame in frames {
+            if let Some(to_send) = dispatcher.handle_frame(frame)? {
+                write_multiple_packets(&mut connection, to_send)?;
+            }
+        }
+
+        match end {
+            Some(DrainEnd::MatchStart(first)) => {
+                let starting_info = connection.recv_starting_info(Some(first))?;
+                write_multiple_packets(&mut connection, dispatcher.restart(starting_info)?)?;
+            }
+            Some(DrainEnd::Stop) => break,
+            None => {}
+        }
+    }
+
+    dispatcher.finish();
+
+    Ok(())
+}
@@ -0,0 +1,234 @@
+//! How runners receive packets: they wait for one, then drain everything that's already
+//! available and sort it into [`Frame`]s, so each runner only has to dispatch them.
+
+use std::mem;
+
+use crate::{
+    BallPredictionRef, GamePacketRef, PacketRef, RLBotConnection, RLBotError, ServerPacket,
+    StartingInfo,
+    flat::{BallPrediction, GamePacket, MatchComm},
+    runner::RunnerConfig,
+    transport::Transport,
+    util::{FramePolicy, UnexpectedPacketPolicy},
+};
+
+/// What a runner passes to the bot for one tick, in this order
+pub(crate) struct Frame<B, G> {
+    pub comms: Vec<MatchComm>,
+    pub ball_prediction: Option<B>,
+    /// `None` if the packets were received without a game packet after them
+    pub game_packet: Option<G>,
+}
+
+impl<B, G> Default for Frame<B, G> {
+    fn default() -> Self {
+        Self {
+            comms: Vec::new(),
+            ball_prediction: None,
+            game_packet: None,
+        }
+    }
+}
+
+/// Sorts comms, ball predictions and game packets received in one go into [`Frame`]s
+pub(crate) struct Frames<B, G> {
+    policy: FramePolicy,
+    done: Vec<Frame<B, G>>,
+    current: Frame<B, G>,
+}
+
+impl<B, G> Frames<B, G> {
+    pub fn new(policy: FramePolicy) -> Self {
+        Self {
+            policy,
+            done: Vec::new(),
+            current: Frame::default(),
+        }
+    }
+
+    pub fn push_comm(&mut self, comm: MatchComm) {
+        self.current.comms.push(comm);
+    }
+
+    pub fn push_ball_prediction(&mut self, ball_prediction: B) {
+        self.current.ball_prediction = Some(ball_prediction);
+    }
+
+    pub fn push_game_packet(&mut self, game_packet: G) {
+        self.current.game_packet = Some(game_packet);
+
+        if self.policy == FramePolicy::EveryPacket {
+            self.done.push(mem::take(&mut self.current));
+        }
+    }
+
+    /// The frames in the order they should be passed to the bot
+    pub fn finish(mut self) -> Vec<Frame<B, G>> {
+        let current = &self.current;
+        if !current.comms.is_empty()
+            || current.ball_prediction.is_some()
+            || current.game_packet.is_some()
+        {
+            self.done.push(self.current);
+        }
+
+        self.done
+    }
+}
+
+/// Everything one drain received
+pub(crate) struct Drained<B, G> {
+    /// Starting info packets sent outside of a match start, already checked
+    /// against the [`UnexpectedPacketPolicy`]. Pass them to their callbacks first.
+    pub others: Vec<ServerPacket>,
+    /// Pass these on in order after `others`
+    pub frames: Vec<Frame<B, G>>,
+    /// What to do afterwards, `None` if the drain ran out of packets
+    pub end: Option<DrainEnd>,
+}
+
+/// Why a drain stopped before running out of packets
+pub(crate) enum DrainEnd {
+    /// A new match started with this packet, receive the rest of its starting info
+    /// with `recv_starting_info`. Anything received before belonged to the previous match.
+    ///
+    /// The server always sends the [`MatchConfiguration`](crate::flat::MatchConfiguration),
+    /// [`FieldInfo`](crate::flat::FieldInfo) and [`ControllableTeamInfo`](crate::flat::ControllableTeamInfo)
+    /// of a match together, in any order, so any one of them starts a new match.
+    /// If one comes alone, the runner waits for the other two and drops the packets in between.
+    MatchStart(ServerPacket),
+    /// The server ended the session
+    Stop,
+}
+
+/// Sorts the packets of one drain, shared by the sync, async and zero-copy drains
+pub(crate) struct Sorter<B, G> {
+    frames: Frames<B, G>,
+    others: Vec<ServerPacket>,
+    close_between_matches: bool,
+    unexpected_packets: UnexpectedPacketPolicy,
+}
+
+impl<B, G> Sorter<B, G> {
+    pub fn new(config: &RunnerConfig) -> Self {
+        Self {
+            frames: Frames::new(config.frame_policy),
+            others: Vec::new(),
+            close_between_matches: config.connection_settings.close_between_matches,
+            unexpected_packets: config.unexpected_packets,
+        }
+    }
+
+    pub fn push_game_packet(&mut self, game_packet: G) {
+        self.frames.push_game_packet(game_packet);
+    }
+
+    pub fn push_ball_prediction(&mut self, ball_prediction: B) {
+        self.frames.push_ball_prediction(ball_prediction);
+    }
+
+    /// Sorts a packet that isn't a game packet or ball prediction,
+    /// returns why the drain has to stop if it does.
+    /// Any part of the starting info starts a new match, see [`DrainEnd::MatchStart`].
+    pub fn push_other(&mut self, packet: ServerPacket) -> Result<Option<DrainEnd>, RLBotError> {
+        match packet {
+            ServerPacket::None => return Ok(Some(DrainEnd::Stop)),
+            ServerPacket::MatchComm(match_comm) => self.frames.push_comm(match_comm),
+            packet if !self.close_between_matches && StartingInfo::is_part(&packet) => {
+                return Ok(Some(DrainEnd::MatchStart(packet)));
+            }
+            packet => self.others.push(self.unexpected_packets.check(packet)?),
+        }
+
+        Ok(None)
+    }
+
+    pub fn finish(self, end: Option<DrainEnd>) -> Drained<B, G> {
+        let frames = match end {
+            // anything received so far belongs to the previous match
+            Some(DrainEnd::MatchStart(_)) => Vec::new(),
+            _ => self.frames.finish(),
+        };
+
+        Drained {
+            others: self.others,
+            frames,
+            end,
+        }
+    }
+}
+
+impl Sorter<BallPrediction, GamePacket> {
+    /// Like [`Self::push_other`], for any packet
+    pub fn push(&mut self, packet: ServerPacket) -> Result<Option<DrainEnd>, RLBotError> {
+        match packet {
+            ServerPacket::GamePacket(gp) => self.push_game_packet(gp),
+            ServerPacket::BallPrediction(ball_pred) => self.push_ball_prediction(ball_pred),
+            packet => return self.push_other(packet),
+        }
+
+        Ok(None)
+    }
+}
+
+/// Waits for one packet, then drains everything that's already available
+pub(crate) fn drain(
+    connection: &mut RLBotConnection<impl Transport>,
+    config: &RunnerConfig,
+) -> Result<Drained<BallPrediction, GamePacket>, RLBotError> {
+    let mut sorter = Sorter::new(config);
+
+    let mut next = Some(connection.recv_packet()?);
+    while let Some(packet) = next {
+        if let Some(end) = sorter.push(packet)? {
+            return Ok(sorter.finish(Some(end)));
+        }
+
+        next = connection.try_recv_packet()?;
+    }
+
+    Ok(sorter.finish(None))
+}
+
+/// Like [`drain`], but game packets and ball predictions are borrowed from the connection
+pub(crate) fn drain_ref<'a>(
+    connection: &'a mut RLBotConnection<impl Transport>,
+    config: &RunnerConfig,
+) -> Result<Drained<BallPredictionRef<'a>, GamePacketRef<'a>>, RLBotError> {
+    let mut sorter = Sorter::new(config);
+
+    for packet in connection.recv_available_ref()? {
+        match packet? {
+            PacketRef::GamePacket(gp) => sorter.push_game_packet(gp),
+            PacketRef::BallPrediction(ball_pred) => sorter.push_ball_prediction(ball_pred),
+            // on a match start, the packets after it stay in the buffer for `recv_starting_info`
+            PacketRef::Other(packet) => {
+                if let Some(end) = sorter.push_other(packet)? {
+                    return Ok(sorter.finish(Some(end)));
+                }
+            }
+        }
+    }
+
+    Ok(sorter.finish(None))
+}
+
+/// Like [`drain`], for [`AsyncRLBotConnection`](crate::asynchronous::AsyncRLBotConnection)
+#[cfg(feature = "tokio")]
+pub(crate) async fn drain_async(
+    connection: &mut crate::asynchronous::AsyncRLBotConnection,
+    config: &RunnerConfig,
+) -> Result<Drained<BallPrediction, GamePacket>, RLBotError> {
+    let mut sorter = Sorter::new(config);
+
+    let mut next = Some(connection.recv_packet().await?);
+    while let Some(packet) = next {
+        if let Some(end) = sorter.push(packet)? {
+            return Ok(sorter.finish(Some(end)));
+        }
+
+        next = connection.try_recv_packet()?;
+    }
+
+    Ok(sorter.finish(None))
+}
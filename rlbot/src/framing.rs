@@ -0,0 +1,256 @@
+use std::{
+    io::{self, Read},
+    ops::Range,
+};
+
+use rlbot_flat::planus;
+
+use crate::{Packet, PacketParseError, RLBotError, connect::map_timeout, flat::RenderGroup};
+
+/// Size of the frame header: a u16 data type followed by a u16 payload length
+pub(crate) const HEADER_LEN: usize = 4;
+/// Size of the biggest payload that fits in a frame
+pub(crate) const MAX_PAYLOAD_LEN: usize = u16::MAX as usize;
+/// Size of the biggest frame RLBotServer can send
+pub(crate) const MAX_FRAME_LEN: usize = HEADER_LEN + MAX_PAYLOAD_LEN;
+
+/// Serializes packets into frames that RLBotServer can understand
+pub(crate) struct FrameEncoder {
+    builder: planus::Builder,
+    /// Reused between calls, so encoding doesn't allocate once it's big enough
+    out: Vec<u8>,
+    /// Split render groups that are too big for one frame instead of erroring,
+    /// giving the extra groups ids from this range
+    pub split_render_groups: Option<Range<i32>>,
+}
+
+impl Default for FrameEncoder {
+    fn default() -> Self {
+        Self {
+            builder: planus::Builder::with_capacity(1024),
+            out: Vec::with_capacity(1024),
+            split_render_groups: None,
+        }
+    }
+}
+
+impl FrameEncoder {
+    /// Returns the frames of all packets, back to back
+    pub fn encode(&mut self, packets: impl Iterator<Item = Packet>) -> Result<&[u8], RLBotError> {
+        self.out.clear();
+
+        // the extra ids are handed out again on every call,
+        // so groups that are sent every tick keep the same ids
+        let mut extra_ids = self.split_render_groups.clone();
+
+        for packet in packets {
+            let data_type = packet.data_type();
+
+            match (packet, &mut extra_ids) {
+                (Packet::RenderGroup(group), Some(extra_ids)) => {
+                    let groups = split_render_group(group, extra_ids, &mut self.builder)
+                        .map_err(|size| RLBotError::PayloadTooLarge { data_type, size })?;
+
+                    for group in groups {
+                        self.encode_packet(&Packet::RenderGroup(group))?;
+                    }
+                }
+                (packet, _) => self.encode_packet(&packet)?,
+            }
+        }
+
+        Ok(&self.out)
+    }
+
+    fn encode_packet(&mut self, packet: &Packet) -> Result<(), RLBotError> {
+        let data_type = packet.data_type();
+        let payload = packet.finish(&mut self.builder);
+        let data_len = u16::try_from(payload.len()).map_err(|_| RLBotError::PayloadTooLarge {
+            data_type,
+            size: payload.len(),
+        })?;
+
+        self.out.extend_from_slice(&data_type.to_be_bytes());
+        self.out.extend_from_slice(&data_len.to_be_bytes());
+        self.out.extend_from_slice(payload);
+
+        Ok(())
+    }
+}
+
+/// Splits `group` into groups that each fit in a frame, keeping the order of the messages.
+/// The first group keeps the original id, the following ones take theirs from `extra_ids`.
+/// Errors with the payload size if a single message doesn't fit or `extra_ids` runs out.
+fn split_render_group(
+    group: RenderGroup,
+    extra_ids: &mut Range<i32>,
+    builder: &mut planus::Builder,
+) -> Result<Vec<RenderGroup>, usize> {
+    let RenderGroup {
+        render_messages,
+        id,
+    } = group;
+
+    let mut pending = vec![render_messages];
+    let mut groups = Vec::with_capacity(1);
+    let mut first_size = None;
+
+    while let Some(render_messages) = pending.pop() {
+        let id = if groups.is_empty() {
+            id
+        } else {
+            extra_ids.start
+        };
+        let candidate = RenderGroup {
+            render_messages,
+            id,
+        };
+
+        builder.clear();
+        let size = builder.finish(&candidate, None).len();
+        let first_size = *first_size.get_or_insert(size);
+        if size <= MAX_PAYLOAD_LEN {
+            if !groups.is_empty() && extra_ids.next().is_none() {
+                return Err(first_size);
+            }
+
+            groups.push(candidate);
+            continue;
+        }
+
+        let mut first_half = candidate.render_messages;
+        if first_half.len() < 2 {
+            // a single message that doesn't fit can't be split any further
+            return Err(size);
+        }
+
+        let second_half = first_half.split_off(first_half.len() / 2);
+        pending.push(second_half);
+        pending.push(first_half);
+    }
+
+    Ok(groups)
+}
+
+/// Buffers incoming bytes and splits them into frames.
+/// Partial frames are kept across reads, so no bytes are lost
+/// no matter how the data is chunked.
+pub(crate) struct FrameDecoder {
+    buf: Box<[u8]>,
+    /// Start of the first unconsumed byte
+    start: usize,
+    /// End of the received bytes
+    end: usize,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self {
+            // Room for at least two full frames so a read can always make progress
+            buf: vec![0u8; 2 * MAX_FRAME_LEN].into_boxed_slice(),
+            start: 0,
+            end: 0,
+        }
+    }
+}
+
+impl FrameDecoder {
+    /// Returns the writable part of the buffer, moving any partial frame to the front first.
+    /// Call [`Self::advance`] with the amount of bytes written into it.
+    pub fn read_space(&mut self) -> &mut [u8] {
+        if self.start > 0 {
+            self.buf.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+        }
+
+        &mut self.buf[self.end..]
+    }
+
+    pub fn advance(&mut self, amount: usize) {
+        self.end += amount;
+        debug_assert!(self.end <= self.buf.len());
+    }
+
+    /// Reads whatever is available from `reader` into the buffer
+    pub fn fill_from(&mut self, reader: &mut impl Read) -> io::Result<()> {
+        let read = loop {
+            match reader.read(self.read_space()) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                res => break res?,
+            }
+        };
+
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        self.advance(read);
+        Ok(())
+    }
+
+    /// Blocks until a full frame is buffered, `reader` has to be in blocking mode
+    pub fn wait_for_frame(&mut self, reader: &mut impl Read) -> Result<(), RLBotError> {
+        while !self.has_frame() {
+            self.fill_from(reader).map_err(map_timeout)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn has_frame(&self) -> bool {
+        frame_len(&self.buf[self.start..self.end]).is_some()
+    }
+
+    /// Pops the next complete frame, returning its data type and payload
+    pub fn next_frame(&mut self) -> Option<(u16, &[u8])> {
+        self.drain_frames().next()
+    }
+
+    /// Pops complete frames as they get iterated over.
+    /// The returned payloads stay valid until the next call to [`Self::read_space`].
+    pub fn drain_frames(&mut self) -> Frames<'_> {
+        Frames {
+            buf: &self.buf[..self.end],
+            start: &mut self.start,
+        }
+    }
+
+    /// Pops and parses the next complete frame
+    pub fn next_packet<P: TryFrom<Packet>>(&mut self) -> Result<Option<P>, PacketParseError> {
+        self.next_frame()
+            .map(|(data_type, payload)| crate::from_payload(data_type, payload))
+            .transpose()
+    }
+}
+
+/// Returns the full length of the first frame in `buf` if it's complete
+fn frame_len(buf: &[u8]) -> Option<usize> {
+    if buf.len() < HEADER_LEN {
+        return None;
+    }
+
+    let data_len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+
+    (buf.len() >= HEADER_LEN + data_len).then_some(HEADER_LEN + data_len)
+}
+
+pub(crate) struct Frames<'a> {
+    buf: &'a [u8],
+    start: &'a mut usize,
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let buf = self.buf;
+        let frame = &buf[*self.start..];
+        let frame_len = frame_len(frame)?;
+        *self.start += frame_len;
+
+        let data_type = u16::from_be_bytes([frame[0], frame[1]]);
+
+        Some((data_type, &frame[HEADER_LEN..frame_len]))
+    }
+}
@@ -0,0 +1,86 @@
+//! Receiving packets with callbacks, for clients that aren't agents, hiveminds or scripts,
+//! e.g. a stats collector or a match orchestrator.
+
+use crate::{
+    RLBotConnection, RLBotError, ServerPacket,
+    flat::{
+        BallPrediction, ControllableTeamInfo, FieldInfo, GamePacket, MatchComm, MatchConfiguration,
+    },
+    transport::Transport,
+    util::{PacketQueue, write_multiple_packets},
+};
+
+/// One callback per packet RLBotServer sends, all of them do nothing by default.
+/// Packets queued in a callback are sent right after it returns.
+#[allow(unused_variables)]
+pub trait PacketHandler {
+    fn on_game_packet(&mut self, game_packet: &GamePacket, packet_queue: &mut PacketQueue) {}
+    fn on_field_info(&mut self, field_info: &FieldInfo, packet_queue: &mut PacketQueue) {}
+    fn on_match_configuration(
+        &mut self,
+        match_configuration: &MatchConfiguration,
+        packet_queue: &mut PacketQueue,
+    ) {
+    }
+    fn on_controllable_team_info(
+        &mut self,
+        controllable_team_info: &ControllableTeamInfo,
+        packet_queue: &mut PacketQueue,
+    ) {
+    }
+    fn on_match_comm(&mut self, match_comm: &MatchComm, packet_queue: &mut PacketQueue) {}
+    fn on_ball_prediction(
+        &mut self,
+        ball_prediction: &BallPrediction,
+        packet_queue: &mut PacketQueue,
+    ) {
+    }
+    /// Called right before [`run_handler`] returns, whether the server ended the session
+    /// with [`ServerPacket::None`] or the connection failed
+    fn on_disconnect(&mut self) {}
+}
+
+/// Passes every received packet to `handler` until the server ends the session.
+/// Nothing is sent beforehand, so send [`ConnectionSettings`](crate::flat::ConnectionSettings) first.
+///
+/// # Errors
+///
+/// Returns an error if there is an error with the connection.
+pub fn run_handler(
+    handler: &mut impl PacketHandler,
+    connection: &mut RLBotConnection<impl Transport>,
+) -> Result<(), RLBotError> {
+    let result = handle_packets(handler, connection);
+    handler.on_disconnect();
+
+    result
+}
+
+fn handle_packets(
+    handler: &mut impl PacketHandler,
+    connection: &mut RLBotConnection<impl Transport>,
+) -> Result<(), RLBotError> {
+    let mut packet_queue = PacketQueue::default();
+
+    loop {
+        match connection.recv_packet()? {
+            ServerPacket::None => break,
+            ServerPacket::GamePacket(x) => handler.on_game_packet(&x, &mut packet_queue),
+            ServerPacket::FieldInfo(x) => handler.on_field_info(&x, &mut packet_queue),
+            ServerPacket::MatchConfiguration(x) => {
+                handler.on_match_configuration(&x, &mut packet_queue);
+            }
+            ServerPacket::ControllableTeamInfo(x) => {
+                handler.on_controllable_team_info(&x, &mut packet_queue);
+            }
+            ServerPacket::MatchComm(x) => handler.on_match_comm(&x, &mut packet_queue),
+            ServerPacket::BallPrediction(x) => handler.on_ball_prediction(&x, &mut packet_queue),
+        }
+
+        if !packet_queue.is_empty() {
+            write_multiple_packets(connection, packet_queue.empty().into_iter())?;
+        }
+    }
+
+    Ok(())
+}
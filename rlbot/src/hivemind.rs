@@ -1,91 +1,256 @@
 use rlbot_flat::flat::{
-    BallPrediction, ConnectionSettings, ControllableTeamInfo, FieldInfo, GamePacket, MatchComm,
+    BallPrediction, BallPredictionRef, ControllableTeamInfo, FieldInfo, GamePacketRef, MatchComm,
     MatchConfiguration,
 };
 
 use crate::{
-    Packet, RLBotConnection, StartingInfo,
-    util::{PacketQueue, write_multiple_packets},
+    ClientPacket, RLBotConnection, ServerPacket, StartingInfo,
+    context::{HivemindContext, TickPacket},
+    dispatch::{self, Bot},
+    drain::{DrainEnd, Drained, drain_ref},
+    runner::RunnerConfig,
+    transport::Transport,
+    util::{InputChecker, PacketQueue, controlled_indices, write_multiple_packets},
 };
 
 #[allow(unused_variables)]
 pub trait Hivemind {
-    fn new(
-        controllable_team_info: ControllableTeamInfo,
-        match_configuration: MatchConfiguration,
-        field_info: FieldInfo,
+    fn new(context: HivemindContext, packet_queue: &mut PacketQueue) -> Self;
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue);
+    fn on_match_comm(&mut self, match_comm: &MatchComm, packet_queue: &mut PacketQueue) {}
+    fn on_ball_prediction(&mut self, ball_prediction: &BallPrediction) {}
+    fn on_match_configuration(
+        &mut self,
+        match_configuration: &MatchConfiguration,
         packet_queue: &mut PacketQueue,
-    ) -> Self;
-    fn tick(&mut self, game_packet: GamePacket, packet_queue: &mut PacketQueue);
-    fn on_match_comm(&mut self, match_comm: MatchComm, packet_queue: &mut PacketQueue) {}
-    fn on_ball_prediction(&mut self, ball_prediction: BallPrediction) {}
+    ) {
+    }
+    fn on_field_info(&mut self, field_info: &FieldInfo, packet_queue: &mut PacketQueue) {}
+    fn on_controllable_team_info(
+        &mut self,
+        controllable_team_info: &ControllableTeamInfo,
+        packet_queue: &mut PacketQueue,
+    ) {
+    }
+    /// Called instead of creating a new hivemind when a new match starts
+    /// and `close_between_matches` is false. Rebuilds the hivemind with [`Self::new`] by default.
+    fn on_match_start(&mut self, context: HivemindContext, packet_queue: &mut PacketQueue)
+    where
+        Self: Sized,
+    {
+        *self = Self::new(context, packet_queue);
+    }
+    /// Called when the match ends, before the next one starts or the runner exits
+    fn on_match_end(&mut self) {}
+}
+
+/// Like [`Hivemind`], but game packets and ball predictions are borrowed
+/// straight from the receive buffer instead of being converted to owned types.
+#[allow(unused_variables)]
+pub trait ZeroCopyHivemind {
+    fn new(context: HivemindContext, packet_queue: &mut PacketQueue) -> Self;
+    fn tick(&mut self, game_packet: GamePacketRef<'_>, packet_queue: &mut PacketQueue);
+    fn on_match_comm(&mut self, match_comm: &MatchComm, packet_queue: &mut PacketQueue) {}
+    fn on_ball_prediction(&mut self, ball_prediction: BallPredictionRef<'_>) {}
+    fn on_match_configuration(
+        &mut self,
+        match_configuration: &MatchConfiguration,
+        packet_queue: &mut PacketQueue,
+    ) {
+    }
+    fn on_field_info(&mut self, field_info: &FieldInfo, packet_queue: &mut PacketQueue) {}
+    fn on_controllable_team_info(
+        &mut self,
+        controllable_team_info: &ControllableTeamInfo,
+        packet_queue: &mut PacketQueue,
+    ) {
+    }
+    /// Called instead of creating a new hivemind when a new match starts
+    /// and `close_between_matches` is false. Rebuilds the hivemind with [`Self::new`] by default.
+    fn on_match_start(&mut self, context: HivemindContext, packet_queue: &mut PacketQueue)
+    where
+        Self: Sized,
+    {
+        *self = Self::new(context, packet_queue);
+    }
+    /// Called when the match ends, before the next one starts or the runner exits
+    fn on_match_end(&mut self) {}
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum HivemindError {
-    #[error("Hivemind panicked")]
-    HivemindPanic,
     #[error("RLBot failed")]
     PacketParseError(#[from] crate::RLBotError),
 }
 
+/// Shorthand for [`Runner::hivemind`](crate::runner::Runner::hivemind), which has more options
 pub fn run_hivemind<T: Hivemind>(
     agent_id: String,
     wants_ball_predictions: bool,
     wants_comms: bool,
-    mut connection: RLBotConnection,
+    connection: RLBotConnection<impl Transport>,
+) -> Result<(), HivemindError> {
+    run_hivemind_with::<T>(
+        RunnerConfig::new(agent_id, wants_ball_predictions, wants_comms),
+        connection,
+    )
+}
+
+pub(crate) fn run_hivemind_with<T: Hivemind>(
+    config: RunnerConfig,
+    connection: RLBotConnection<impl Transport>,
+) -> Result<(), HivemindError> {
+    Ok(dispatch::run(&config, connection, HivemindBot::<T>::new)?)
+}
+
+/// A [`Hivemind`] as the runners see it
+pub(crate) struct HivemindBot<T>(T);
+
+impl<T: Hivemind> HivemindBot<T> {
+    pub fn new(starting_info: StartingInfo, packet_queue: &mut PacketQueue) -> Self {
+        Self(T::new(HivemindContext::from(starting_info), packet_queue))
+    }
+}
+
+impl<T: Hivemind> Bot for HivemindBot<T> {
+    const CONTROLS_CARS: bool = true;
+
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        self.0.tick(game_packet, packet_queue);
+    }
+
+    fn on_match_comm(&mut self, match_comm: &MatchComm, packet_queue: &mut PacketQueue) {
+        self.0.on_match_comm(match_comm, packet_queue);
+    }
+
+    fn on_ball_prediction(&mut self, ball_prediction: &BallPrediction) {
+        self.0.on_ball_prediction(ball_prediction);
+    }
+
+    fn on_match_configuration(
+        &mut self,
+        match_configuration: &MatchConfiguration,
+        packet_queue: &mut PacketQueue,
+    ) {
+        self.0
+            .on_match_configuration(match_configuration, packet_queue);
+    }
+
+    fn on_field_info(&mut self, field_info: &FieldInfo, packet_queue: &mut PacketQueue) {
+        self.0.on_field_info(field_info, packet_queue);
+    }
+
+    fn on_controllable_team_info(
+        &mut self,
+        controllable_team_info: &ControllableTeamInfo,
+        packet_queue: &mut PacketQueue,
+    ) {
+        self.0
+            .on_controllable_team_info(controllable_team_info, packet_queue);
+    }
+
+    fn restart(&mut self, starting_info: StartingInfo, packet_queue: &mut PacketQueue) {
+        self.0.on_match_end();
+        self.0
+            .on_match_start(HivemindContext::from(starting_info), packet_queue);
+    }
+
+    fn on_match_end(&mut self) {
+        self.0.on_match_end();
+    }
+}
+
+/// Shorthand for [`Runner::hivemind`](crate::runner::Runner::hivemind), which has more options
+pub fn run_zero_copy_hivemind<T: ZeroCopyHivemind>(
+    agent_id: String,
+    wants_ball_predictions: bool,
+    wants_comms: bool,
+    connection: RLBotConnection<impl Transport>,
 ) -> Result<(), HivemindError> {
-    connection.send_packet(ConnectionSettings {
-        agent_id,
-        wants_ball_predictions,
-        wants_comms,
-        close_between_matches: true,
-    })?;
-
-    let StartingInfo {
-        controllable_team_info,
-        match_configuration,
-        field_info,
-    } = connection.get_starting_info()?;
+    run_zero_copy_hivemind_with::<T>(
+        RunnerConfig::new(agent_id, wants_ball_predictions, wants_comms),
+        connection,
+    )
+}
 
+pub(crate) fn run_zero_copy_hivemind_with<T: ZeroCopyHivemind>(
+    config: RunnerConfig,
+    mut connection: RLBotConnection<impl Transport>,
+) -> Result<(), HivemindError> {
+    let mut input_checker = InputChecker::new(&config);
+    connection.send_packet(config.connection_settings.clone())?;
+
+    let context = HivemindContext::from(connection.get_starting_info()?);
+
+    let mut controlled = controlled_indices(&context.controllable_team_info);
     let mut outgoing_queue = PacketQueue::default();
-    let mut hivemind = T::new(
-        controllable_team_info,
-        match_configuration,
-        field_info,
-        &mut outgoing_queue,
-    );
-
-    outgoing_queue.push(Packet::InitComplete);
-    write_multiple_packets(&mut connection, outgoing_queue.empty().into_iter())?;
-
-    let mut ball_prediction = None;
-    let mut game_packet = None;
-    'main_loop: loop {
-        connection.set_nonblocking(true)?;
-        while let Ok(packet) = connection.recv_packet() {
+    let mut hivemind = T::new(context, &mut outgoing_queue);
+
+    outgoing_queue.push(ClientPacket::InitComplete);
+    write_multiple_packets(
+        &mut connection,
+        outgoing_queue.empty_checked(&mut input_checker, &controlled)?,
+    )?;
+
+    let mut to_send = Vec::new();
+    loop {
+        let Drained {
+            others,
+            frames,
+            end,
+        } = drain_ref(&mut connection, &config)?;
+
+        for packet in others {
             match packet {
-                Packet::None => break 'main_loop,
-                Packet::MatchComm(match_comm) => {
-                    hivemind.on_match_comm(match_comm, &mut outgoing_queue);
+                ServerPacket::MatchConfiguration(x) => {
+                    hivemind.on_match_configuration(&x, &mut outgoing_queue);
+                }
+                ServerPacket::FieldInfo(x) => hivemind.on_field_info(&x, &mut outgoing_queue),
+                ServerPacket::ControllableTeamInfo(x) => {
+                    hivemind.on_controllable_team_info(&x, &mut outgoing_queue);
                 }
-                Packet::BallPrediction(ball_pred) => ball_prediction = Some(ball_pred),
-                Packet::GamePacket(gp) => game_packet = Some(gp),
-                _ => panic!("Unexpected packet: {packet:?}"),
+                _ => {}
             }
         }
-        connection.set_nonblocking(false)?;
 
-        if let Some(game_packet) = game_packet.take() {
-            if let Some(ball_prediction) = ball_prediction.take() {
+        // the frames borrow the connection's buffer, so everything is sent at once afterwards
+        for frame in frames {
+            for match_comm in frame.comms {
+                hivemind.on_match_comm(&match_comm, &mut outgoing_queue);
+            }
+
+            if let Some(ball_prediction) = frame.ball_prediction {
                 hivemind.on_ball_prediction(ball_prediction);
             }
 
-            hivemind.tick(game_packet, &mut outgoing_queue);
+            if let Some(game_packet) = frame.game_packet {
+                hivemind.tick(game_packet, &mut outgoing_queue);
 
-            write_multiple_packets(&mut connection, outgoing_queue.empty().into_iter())?;
+                to_send.extend(outgoing_queue.empty_checked(&mut input_checker, &controlled)?);
+            }
+        }
+
+        write_multiple_packets(&mut connection, to_send.drain(..))?;
+
+        match end {
+            Some(DrainEnd::MatchStart(first)) => {
+                let context = HivemindContext::from(connection.recv_starting_info(Some(first))?);
+                controlled = controlled_indices(&context.controllable_team_info);
+                hivemind.on_match_end();
+                hivemind.on_match_start(context, &mut outgoing_queue);
+
+                outgoing_queue.push(ClientPacket::InitComplete);
+                write_multiple_packets(
+                    &mut connection,
+                    outgoing_queue.empty_checked(&mut input_checker, &controlled)?,
+                )?;
+            }
+            Some(DrainEnd::Stop) => break,
+            None => {}
         }
     }
 
+    hivemind.on_match_end();
+
     Ok(())
 }
@@ -1,21 +1,43 @@
 use std::{
-    io::{Read, Write},
+    io,
     net::{AddrParseError, SocketAddr, TcpStream},
+    ops::Range,
     str::FromStr,
+    time::Duration,
 };
 
-use rlbot_flat::planus::{self, ReadAsRoot};
+use connect::{ConnectOptions, map_timeout};
+use framing::{FrameDecoder, FrameEncoder};
+use rlbot_flat::planus::ReadAsRoot;
+use split::{PacketReader, PacketWriter};
 use thiserror::Error;
+use transport::Transport;
 
 pub mod agents;
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+mod broadcast;
+pub mod connect;
+pub mod context;
+mod dispatch;
+mod drain;
+mod framing;
+pub mod handler;
 pub mod hivemind;
+pub mod recording;
+pub mod replay;
+pub mod runner;
 pub mod scripts;
+pub mod split;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod transport;
 pub mod util;
 
 #[cfg(feature = "glam")]
 pub use rlbot_flat::glam;
 
-pub use rlbot_flat::flat;
+pub use rlbot_flat::{flat, planus};
 
 use flat::*;
 
@@ -23,8 +45,18 @@ use flat::*;
 pub enum PacketParseError {
     #[error("Invalid data type: {0}")]
     InvalidDataType(u16),
-    #[error("Unpacking flatbuffer failed")]
-    InvalidFlatbuffer(#[from] planus::Error),
+    #[error("Unpacking flatbuffer with data type {data_type} failed")]
+    InvalidFlatbuffer {
+        data_type: u16,
+        source: planus::Error,
+    },
+    #[error("Converting flatbuffer with data type {data_type} failed")]
+    ConversionFailed {
+        data_type: u16,
+        source: planus::Error,
+    },
+    #[error("Packets with data type {0} aren't sent in this direction")]
+    WrongDirection(u16),
 }
 
 #[derive(Error, Debug)]
@@ -35,8 +67,24 @@ pub enum RLBotError {
     PacketParseError(#[from] PacketParseError),
     #[error("Invalid address, cannot parse")]
     InvalidAddrError(#[from] AddrParseError),
+    #[error("Payload with data type {data_type} is {size} bytes, which doesn't fit in a u16")]
+    PayloadTooLarge { data_type: u16, size: usize },
+    #[error("Invalid session recording: {0}")]
+    InvalidRecording(&'static str),
+    #[error("Timed out while communicating with RLBot")]
+    Timeout,
+    #[error("Received unexpected packet with data type {}", .0.data_type())]
+    UnexpectedPacket(Box<Packet>),
+    #[error("No agent id was given and RLBOT_AGENT_ID isn't set")]
+    MissingAgentId,
+    #[error("PlayerInput for index {0}, which isn't controlled by this bot")]
+    ForeignPlayerInput(u32),
+    #[error("More than one PlayerInput for index {0} at once")]
+    DuplicatePlayerInput(u32),
 }
 
+/// Every packet in either direction, for tooling like [`recording`].
+/// Connections send [`ClientPacket`]s and receive [`ServerPacket`]s.
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub enum Packet {
@@ -89,6 +137,107 @@ gen_impl_from_flat_packet!(
     ControllableTeamInfo
 );
 
+/// The packets RLBotServer sends to bots
+#[derive(Debug, Clone)]
+pub enum ServerPacket {
+    /// The session is over
+    None,
+    GamePacket(GamePacket),
+    FieldInfo(FieldInfo),
+    MatchConfiguration(MatchConfiguration),
+    MatchComm(MatchComm),
+    BallPrediction(BallPrediction),
+    ControllableTeamInfo(ControllableTeamInfo),
+}
+
+/// The packets bots send to RLBotServer
+#[derive(Debug, Clone)]
+pub enum ClientPacket {
+    None,
+    StartCommand(StartCommand),
+    MatchConfiguration(MatchConfiguration),
+    PlayerInput(PlayerInput),
+    DesiredGameState(DesiredGameState),
+    RenderGroup(RenderGroup),
+    RemoveRenderGroup(RemoveRenderGroup),
+    MatchComm(MatchComm),
+    ConnectionSettings(ConnectionSettings),
+    StopCommand(StopCommand),
+    SetLoadout(SetLoadout),
+    InitComplete,
+}
+
+macro_rules! gen_packet_direction {
+    ($name:ident; $($empty:ident),+; $($x:ident),+) => {
+        impl From<$name> for Packet {
+            fn from(packet: $name) -> Self {
+                match packet {
+                    $(
+                        $name::$empty => Packet::$empty,
+                    )+
+                    $(
+                        $name::$x(x) => Packet::$x(x),
+                    )+
+                }
+            }
+        }
+
+        /// Gives the packet back if it's not sent in this direction
+        impl TryFrom<Packet> for $name {
+            type Error = Packet;
+
+            fn try_from(packet: Packet) -> Result<Self, Packet> {
+                match packet {
+                    $(
+                        Packet::$empty => Ok(Self::$empty),
+                    )+
+                    $(
+                        Packet::$x(x) => Ok(Self::$x(x)),
+                    )+
+                    packet => Err(packet),
+                }
+            }
+        }
+
+        $(
+            impl From<$x> for $name {
+                fn from(x: $x) -> Self {
+                    Self::$x(x)
+                }
+            }
+        )+
+
+        impl $name {
+            /// Like [`Packet::from_payload`], but fails for packets sent in the other direction
+            pub fn from_payload(data_type: u16, payload: &[u8]) -> Result<Self, PacketParseError> {
+                from_payload(data_type, payload)
+            }
+        }
+    };
+}
+
+gen_packet_direction!(
+    ServerPacket;
+    None;
+    GamePacket, FieldInfo, MatchConfiguration, MatchComm, BallPrediction, ControllableTeamInfo
+);
+
+gen_packet_direction!(
+    ClientPacket;
+    None, InitComplete;
+    StartCommand, MatchConfiguration, PlayerInput, DesiredGameState, RenderGroup,
+    RemoveRenderGroup, MatchComm, ConnectionSettings, StopCommand, SetLoadout
+);
+
+/// Parses a [`Packet`] and narrows it down to `P`
+pub(crate) fn from_payload<P: TryFrom<Packet>>(
+    data_type: u16,
+    payload: &[u8],
+) -> Result<P, PacketParseError> {
+    P::try_from(Packet::from_payload(data_type, payload)?)
+        .map_err(|_| PacketParseError::WrongDirection(data_type))
+}
+
 impl Packet {
     #[must_use]
     pub const fn data_type(&self) -> u16 {
@@ -113,17 +262,22 @@ impl Packet {
     }
 
     pub fn build(self, builder: &mut planus::Builder) -> Vec<u8> {
+        self.finish(builder).to_vec()
+    }
+
+    /// Like [`Self::build`], but borrows the payload from `builder` instead of copying it out
+    pub fn finish<'a>(&self, builder: &'a mut planus::Builder) -> &'a [u8] {
         // TODO: make this mess nicer
         macro_rules! p {
             ($($x:ident),+; $($y:ident),+) => {
                 match self {
                     $(
-                        Self::$x => Vec::new(),
+                        Self::$x => &[],
                     )+
                     $(
                         Self::$y(x) => {
                             builder.clear();
-                            builder.finish(x, None).to_vec()
+                            builder.finish(x, None)
                         },
                     )+
                 }
@@ -147,7 +301,11 @@ impl Packet {
                 Ok(Self::$e)
             };
             ($e:ident, $x:ident) => {
-                Ok(Self::$e($x::read_as_root(payload)?.try_into().unwrap()))
+                $x::read_as_root(payload)
+                    .map_err(|source| PacketParseError::InvalidFlatbuffer { data_type, source })?
+                    .try_into()
+                    .map(Self::$e)
+                    .map_err(|source| PacketParseError::ConversionFailed { data_type, source })
             };
             ($($n:literal, $($x:ident),+);+) => {
                 match data_type {
@@ -183,99 +341,341 @@ impl Packet {
     }
 }
 
+/// A packet borrowed from the receive buffer of a connection.
+/// The packets RLBotServer sends every tick are kept as flatbuffer views,
+/// everything else is converted to an owned [`ServerPacket`].
+#[derive(Debug)]
+pub enum PacketRef<'a> {
+    GamePacket(GamePacketRef<'a>),
+    BallPrediction(BallPredictionRef<'a>),
+    Other(ServerPacket),
+}
+
+impl<'a> PacketRef<'a> {
+    pub fn from_payload(data_type: u16, payload: &'a [u8]) -> Result<Self, PacketParseError> {
+        let invalid = |source| PacketParseError::InvalidFlatbuffer { data_type, source };
+
+        match data_type {
+            1 => Ok(Self::GamePacket(
+                GamePacketRef::read_as_root(payload).map_err(invalid)?,
+            )),
+            10 => Ok(Self::BallPrediction(
+                BallPredictionRef::read_as_root(payload).map_err(invalid)?,
+            )),
+            _ => ServerPacket::from_payload(data_type, payload).map(Self::Other),
+        }
+    }
+}
+
 pub struct StartingInfo {
     pub controllable_team_info: ControllableTeamInfo,
     pub match_configuration: MatchConfiguration,
     pub field_info: FieldInfo,
 }
 
-pub struct RLBotConnection {
-    stream: TcpStream,
-    builder: planus::Builder,
-    recv_buf: Box<[u8; u16::MAX as usize]>,
+impl StartingInfo {
+    /// Whether `packet` is part of the starting info, which means a new match started
+    /// if it's received after the first one
+    pub(crate) const fn is_part(packet: &ServerPacket) -> bool {
+        matches!(
+            packet,
+            ServerPacket::ControllableTeamInfo(_)
+                | ServerPacket::MatchConfiguration(_)
+                | ServerPacket::FieldInfo(_)
+        )
+    }
+}
+
+/// Collects the packets that make up a [`StartingInfo`], in any order
+#[derive(Default)]
+pub(crate) struct StartingInfoCollector {
+    controllable_team_info: Option<ControllableTeamInfo>,
+    match_configuration: Option<MatchConfiguration>,
+    field_info: Option<FieldInfo>,
+}
+
+impl StartingInfoCollector {
+    /// Returns the starting info once all of it has been collected, ignores other packets
+    pub fn add(&mut self, packet: ServerPacket) -> Option<StartingInfo> {
+        match packet {
+            ServerPacket::ControllableTeamInfo(x) => self.controllable_team_info = Some(x),
+            ServerPacket::MatchConfiguration(x) => self.match_configuration = Some(x),
+            ServerPacket::FieldInfo(x) => self.field_info = Some(x),
+            _ => {}
+        }
+
+        if self.controllable_team_info.is_none()
+            || self.match_configuration.is_none()
+            || self.field_info.is_none()
+        {
+            return None;
+        }
+
+        Some(StartingInfo {
+            controllable_team_info: self.controllable_team_info.take()?,
+            match_configuration: self.match_configuration.take()?,
+            field_info: self.field_info.take()?,
+        })
+    }
+}
+
+/// A connection to RLBotServer, over TCP by default but any [`Transport`] works
+pub struct RLBotConnection<S: Transport = TcpStream> {
+    stream: S,
+    nonblocking: bool,
+    encoder: FrameEncoder,
+    recv_buf: FrameDecoder,
 }
 
 impl RLBotConnection {
-    fn send_packet_enum(&mut self, packet: Packet) -> Result<(), RLBotError> {
-        let data_type_bin = packet.data_type().to_be_bytes().to_vec();
-        let payload = packet.build(&mut self.builder);
-        let data_len_bin = u16::try_from(payload.len())
-            .expect("Payload can't be greater than a u16")
-            .to_be_bytes()
-            .to_vec();
-
-        // Join so we make sure everything gets written in the right order
-        let joined = [data_type_bin, data_len_bin, payload].concat();
-
-        self.stream.write_all(&joined)?;
-        self.stream.flush()?;
+    pub fn new(addr: &str) -> Result<Self, RLBotError> {
+        Self::connect(addr, &ConnectOptions::default())
+    }
+
+    /// Connects with retries and timeouts, see [`ConnectOptions`].
+    /// Reads and writes that exceed the configured timeouts fail with [`RLBotError::Timeout`].
+    pub fn connect(addr: &str, options: &ConnectOptions) -> Result<Self, RLBotError> {
+        let stream = options.connect(SocketAddr::from_str(addr)?)?;
+
+        stream.set_nodelay(true)?;
+
+        Ok(Self::from_transport(stream))
+    }
+
+    pub fn set_nodelay(&self, nodelay: bool) -> Result<(), RLBotError> {
+        self.stream.set_nodelay(nodelay)?;
+        Ok(())
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), RLBotError> {
+        self.stream.set_read_timeout(timeout)?;
+        Ok(())
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> Result<(), RLBotError> {
+        self.stream.set_write_timeout(timeout)?;
         Ok(())
     }
+}
+
+impl<S: Transport> RLBotConnection<S> {
+    /// Uses an already established stream, e.g. a unix socket or a [`transport::MemoryStream`]
+    pub fn from_transport(stream: S) -> Self {
+        Self {
+            stream,
+            nonblocking: false,
+            encoder: FrameEncoder::default(),
+            recv_buf: FrameDecoder::default(),
+        }
+    }
 
-    pub fn send_packet(&mut self, packet: impl Into<Packet>) -> Result<(), RLBotError> {
-        self.send_packet_enum(packet.into())
+    /// Returns the underlying stream, dropping any packets that were buffered but not received yet
+    pub fn into_transport(self) -> S {
+        self.stream
     }
 
-    pub fn recv_packet(&mut self) -> Result<Packet, RLBotError> {
-        let mut buf = [0u8; 4];
+    /// Splits the connection into halves that can be used from different threads,
+    /// see [`PacketReader::reunite`] to turn them back into a connection.
+    /// Packets that were already buffered can still be received from the reader.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the transport doesn't support [`Transport::try_clone`].
+    pub fn split(mut self) -> Result<(PacketReader<S>, PacketWriter<S>), RLBotError> {
+        // Both halves share the blocking mode of the stream, and the writer needs it blocking
+        self.set_nonblocking(false)?;
+        let write_stream = self.stream.try_clone()?;
+
+        Ok(split::new(
+            self.stream,
+            self.recv_buf,
+            write_stream,
+            self.encoder,
+        ))
+    }
 
-        self.stream.read_exact(&mut buf)?;
+    pub fn send_packet(&mut self, packet: impl Into<ClientPacket>) -> Result<(), RLBotError> {
+        self.send_packets([packet.into()])
+    }
 
-        let data_type = u16::from_be_bytes([buf[0], buf[1]]);
-        let data_len = u16::from_be_bytes([buf[2], buf[3]]);
+    /// Encodes all packets first and then writes them at once, blocking until everything has been written.
+    /// The frames are encoded into a buffer that's reused between calls.
+    pub fn send_packets(
+        &mut self,
+        packets: impl IntoIterator<Item = ClientPacket>,
+    ) -> Result<(), RLBotError> {
+        self.write_packets(packets.into_iter().map(Packet::from))
+    }
 
-        let buf = &mut self.recv_buf[0..data_len as usize];
+    /// Like [`Self::send_packets`], but in either direction, e.g. for [`testing`]
+    pub(crate) fn write_packets(
+        &mut self,
+        packets: impl IntoIterator<Item = Packet>,
+    ) -> Result<(), RLBotError> {
+        self.set_nonblocking(false)?;
 
-        self.stream.read_exact(buf)?;
+        let to_write = self.encoder.encode(packets.into_iter())?;
 
-        let packet = Packet::from_payload(data_type, buf)?;
+        self.stream.write_all(to_write).map_err(map_timeout)?;
+        self.stream.flush().map_err(map_timeout)?;
+        Ok(())
+    }
 
-        Ok(packet)
+    /// Reads whatever is available into the receive buffer
+    fn fill_recv_buf(&mut self) -> Result<(), RLBotError> {
+        Ok(self.recv_buf.fill_from(&mut self.stream)?)
     }
 
-    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), RLBotError> {
-        self.stream.set_nonblocking(nonblocking)?;
+    /// Blocks until a full frame is buffered
+    fn wait_for_frame(&mut self) -> Result<(), RLBotError> {
+        if self.recv_buf.has_frame() {
+            return Ok(());
+        }
+
+        self.set_nonblocking(false)?;
+        self.recv_buf.wait_for_frame(&mut self.stream)
+    }
+
+    /// Switches to non-blocking reads, returns `false` if the transport doesn't support them
+    fn enable_nonblocking(&mut self) -> Result<bool, RLBotError> {
+        match self.set_nonblocking(true) {
+            Ok(()) => Ok(true),
+            Err(RLBotError::Connection(e)) if e.kind() == io::ErrorKind::Unsupported => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Buffers everything that can be read without blocking
+    fn read_available(&mut self) -> Result<(), RLBotError> {
+        if !self.enable_nonblocking()? {
+            return Ok(());
+        }
+
+        while !self.recv_buf.read_space().is_empty() {
+            match self.fill_recv_buf() {
+                Ok(()) => {}
+                Err(RLBotError::Connection(e)) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
         Ok(())
     }
 
-    pub fn new(addr: &str) -> Result<Self, RLBotError> {
-        let stream = TcpStream::connect(SocketAddr::from_str(addr)?)?;
+    /// Blocks until a full packet has been received
+    pub fn recv_packet(&mut self) -> Result<ServerPacket, RLBotError> {
+        self.read_packet()
+    }
 
-        stream.set_nodelay(true)?;
+    /// Like [`Self::recv_packet`], but in either direction, e.g. for [`testing`]
+    pub(crate) fn read_packet<P: TryFrom<Packet>>(&mut self) -> Result<P, RLBotError> {
+        self.wait_for_frame()?;
 
-        Ok(Self {
-            stream,
-            builder: planus::Builder::with_capacity(1024),
-            recv_buf: Box::new([0u8; u16::MAX as usize]),
-        })
+        Ok(self
+            .recv_buf
+            .next_packet()?
+            .expect("a full frame should be buffered"))
     }
 
-    pub fn get_starting_info(&mut self) -> Result<StartingInfo, RLBotError> {
-        let mut controllable_team_info = None;
-        let mut match_configuration = None;
-        let mut field_info = None;
+    /// Like [`Self::recv_packet`], but game packets and ball predictions
+    /// are borrowed from the receive buffer instead of being converted.
+    pub fn recv_packet_ref(&mut self) -> Result<PacketRef<'_>, RLBotError> {
+        self.wait_for_frame()?;
+
+        let (data_type, payload) = self
+            .recv_buf
+            .next_frame()
+            .expect("a full frame should be buffered");
+
+        Ok(PacketRef::from_payload(data_type, payload)?)
+    }
+
+    /// Blocks until at least one packet has been received, then returns
+    /// every packet that can be received without blocking as a [`PacketRef`].
+    /// Packets that aren't iterated over are kept for later calls.
+    pub fn recv_available_ref(
+        &mut self,
+    ) -> Result<impl Iterator<Item = Result<PacketRef<'_>, PacketParseError>>, RLBotError> {
+        self.wait_for_frame()?;
+        self.read_available()?;
+
+        Ok(self
+            .recv_buf
+            .drain_frames()
+            .map(|(data_type, payload)| PacketRef::from_payload(data_type, payload)))
+    }
+
+    /// Returns the next packet if it can be received without blocking.
+    /// Partially received packets are kept and completed by later calls.
+    /// If the transport doesn't support non-blocking reads, only already buffered packets are returned.
+    pub fn try_recv_packet(&mut self) -> Result<Option<ServerPacket>, RLBotError> {
+        self.try_read_packet()
+    }
+
+    /// Like [`Self::try_recv_packet`], but in either direction, e.g. for [`testing`]
+    pub(crate) fn try_read_packet<P: TryFrom<Packet>>(&mut self) -> Result<Option<P>, RLBotError> {
+        if let Some(packet) = self.recv_buf.next_packet()? {
+            return Ok(Some(packet));
+        }
+
+        if !self.enable_nonblocking()? {
+            return Ok(None);
+        }
 
         loop {
-            let packet = self.recv_packet()?;
-            match packet {
-                Packet::ControllableTeamInfo(x) => controllable_team_info = Some(x),
-                Packet::MatchConfiguration(x) => match_configuration = Some(x),
-                Packet::FieldInfo(x) => field_info = Some(x),
-                _ => {}
+            match self.fill_recv_buf() {
+                Ok(()) => {}
+                Err(RLBotError::Connection(e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(None);
+                }
+                Err(e) => return Err(e),
             }
 
-            if controllable_team_info.is_some()
-                && match_configuration.is_some()
-                && field_info.is_some()
-            {
-                break;
+            if let Some(packet) = self.recv_buf.next_packet()? {
+                return Ok(Some(packet));
             }
         }
+    }
 
-        Ok(StartingInfo {
-            controllable_team_info: controllable_team_info.unwrap(),
-            match_configuration: match_configuration.unwrap(),
-            field_info: field_info.unwrap(),
-        })
+    /// With `Some(extra_ids)`, a [`RenderGroup`] that's too big for one packet is split
+    /// into several groups instead of returning [`RLBotError::PayloadTooLarge`].
+    /// The first group keeps its id, the others take ids from `extra_ids` in order,
+    /// starting over with every [`Self::send_packets`] call. Reserve the range for this,
+    /// and remove its groups with [`RemoveRenderGroup`](crate::flat::RemoveRenderGroup)
+    /// once they aren't needed anymore. Sending fails with [`RLBotError::PayloadTooLarge`]
+    /// if the range runs out or a single message doesn't fit.
+    pub fn set_split_render_groups(&mut self, extra_ids: Option<Range<i32>>) {
+        self.encoder.split_render_groups = extra_ids;
+    }
+
+    pub fn set_nonblocking(&mut self, nonblocking: bool) -> Result<(), RLBotError> {
+        if self.nonblocking != nonblocking {
+            self.stream.set_nonblocking(nonblocking)?;
+            self.nonblocking = nonblocking;
+        }
+        Ok(())
+    }
+
+    pub fn get_starting_info(&mut self) -> Result<StartingInfo, RLBotError> {
+        self.recv_starting_info(None)
+    }
+
+    /// Like [`Self::get_starting_info`], but starts with an already received packet
+    pub(crate) fn recv_starting_info(
+        &mut self,
+        mut first: Option<ServerPacket>,
+    ) -> Result<StartingInfo, RLBotError> {
+        let mut collector = StartingInfoCollector::default();
+
+        loop {
+            let packet = match first.take() {
+                Some(packet) => packet,
+                None => self.recv_packet()?,
+            };
+
+            if let Some(starting_info) = collector.add(packet) {
+                return Ok(starting_info);
+            }
+        }
     }
 }
@@ -0,0 +1,233 @@
+//! Recording every frame of a session to a compact binary file, and reading it back.
+//!
+//! A session file starts with [`MAGIC`], followed by one record per frame:
+//! direction (u8), microseconds since the start of the recording (u64 LE),
+//! data type (u16 BE), payload length (u16 BE) and the raw payload.
+
+use std::{
+    io::{self, Read, Write},
+    time::{Duration, Instant},
+};
+
+use crate::{Packet, RLBotError, framing::HEADER_LEN, transport::Transport};
+
+pub const MAGIC: &[u8; 8] = b"RLBOTREC";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent by RLBotServer to the bot
+    Received = 0,
+    /// Sent by the bot to RLBotServer
+    Sent = 1,
+}
+
+/// Splits one direction of a byte stream back into frames
+#[derive(Default)]
+struct FrameSplitter {
+    buf: Vec<u8>,
+}
+
+impl FrameSplitter {
+    fn push(
+        &mut self,
+        bytes: &[u8],
+        mut on_frame: impl FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        self.buf.extend_from_slice(bytes);
+
+        let mut start = 0;
+        while self.buf.len() - start >= HEADER_LEN {
+            let data_len = u16::from_be_bytes([self.buf[start + 2], self.buf[start + 3]]) as usize;
+            let frame_len = HEADER_LEN + data_len;
+            if self.buf.len() - start < frame_len {
+                break;
+            }
+
+            on_frame(&self.buf[start..start + frame_len])?;
+            start += frame_len;
+        }
+
+        self.buf.drain(..start);
+        Ok(())
+    }
+}
+
+/// A [`Transport`] that records every frame going through `stream` into `output`.
+/// Pass it to [`RLBotConnection::from_transport`](crate::RLBotConnection::from_transport)
+/// to record a session while running a bot as usual.
+pub struct Recorder<S: Transport, W: Write> {
+    stream: S,
+    output: W,
+    start: Instant,
+    received: FrameSplitter,
+    sent: FrameSplitter,
+}
+
+impl<S: Transport, W: Write> Recorder<S, W> {
+    pub fn new(stream: S, mut output: W) -> Result<Self, RLBotError> {
+        output.write_all(MAGIC)?;
+
+        Ok(Self {
+            stream,
+            output,
+            start: Instant::now(),
+            received: FrameSplitter::default(),
+            sent: FrameSplitter::default(),
+        })
+    }
+
+    /// Returns the wrapped stream and the output
+    pub fn into_inner(self) -> (S, W) {
+        (self.stream, self.output)
+    }
+}
+
+fn write_record(
+    output: &mut impl Write,
+    direction: Direction,
+    timestamp: Duration,
+    frame: &[u8],
+) -> io::Result<()> {
+    let micros = u64::try_from(timestamp.as_micros()).unwrap_or(u64::MAX);
+
+    output.write_all(&[direction as u8])?;
+    output.write_all(&micros.to_le_bytes())?;
+    output.write_all(frame)
+}
+
+impl<S: Transport, W: Write> Read for Recorder<S, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stream.read(buf)?;
+        let timestamp = self.start.elapsed();
+
+        let output = &mut self.output;
+        self.received.push(&buf[..read], |frame| {
+            write_record(output, Direction::Received, timestamp, frame)
+        })?;
+
+        Ok(read)
+    }
+}
+
+impl<S: Transport, W: Write> Write for Recorder<S, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.stream.write(buf)?;
+        let timestamp = self.start.elapsed();
+
+        let output = &mut self.output;
+        self.sent.push(&buf[..written], |frame| {
+            write_record(output, Direction::Sent, timestamp, frame)
+        })?;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()?;
+        self.output.flush()
+    }
+}
+
+impl<S: Transport, W: Write> Transport for Recorder<S, W> {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.stream.set_nonblocking(nonblocking)
+    }
+}
+
+/// A frame read back from a session file
+#[derive(Debug, Clone)]
+pub struct RecordedFrame {
+    pub direction: Direction,
+    /// Time since the start of the recording
+    pub timestamp: Duration,
+    pub data_type: u16,
+    pub payload: Vec<u8>,
+}
+
+impl RecordedFrame {
+    pub fn to_packet(&self) -> Result<Packet, RLBotError> {
+        Ok(Packet::from_payload(self.data_type, &self.payload)?)
+    }
+}
+
+/// A packet read back from a session file
+#[derive(Debug, Clone)]
+pub struct RecordedPacket {
+    pub direction: Direction,
+    /// Time since the start of the recording
+    pub timestamp: Duration,
+    pub packet: Packet,
+}
+
+/// Iterates over the frames of a session file
+pub struct SessionReader<R: Read> {
+    input: R,
+}
+
+impl<R: Read> SessionReader<R> {
+    pub fn new(mut input: R) -> Result<Self, RLBotError> {
+        let mut magic = [0; MAGIC.len()];
+        input.read_exact(&mut magic)?;
+
+        if &magic != MAGIC {
+            return Err(RLBotError::InvalidRecording("missing magic bytes"));
+        }
+
+        Ok(Self { input })
+    }
+
+    /// Reads the next frame, or `None` at the end of the file
+    pub fn next_frame(&mut self) -> Result<Option<RecordedFrame>, RLBotError> {
+        let mut direction = [0; 1];
+        match self.input.read_exact(&mut direction) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let direction = match direction[0] {
+            0 => Direction::Received,
+            1 => Direction::Sent,
+            _ => return Err(RLBotError::InvalidRecording("invalid direction")),
+        };
+
+        let mut header = [0; 8 + HEADER_LEN];
+        self.input.read_exact(&mut header)?;
+
+        let timestamp = Duration::from_micros(u64::from_le_bytes(header[..8].try_into().unwrap()));
+        let data_type = u16::from_be_bytes([header[8], header[9]]);
+        let data_len = u16::from_be_bytes([header[10], header[11]]);
+
+        let mut payload = vec![0; data_len as usize];
+        self.input.read_exact(&mut payload)?;
+
+        Ok(Some(RecordedFrame {
+            direction,
+            timestamp,
+            data_type,
+            payload,
+        }))
+    }
+
+    /// Iterates over the raw frames instead of parsed packets
+    pub fn frames(mut self) -> impl Iterator<Item = Result<RecordedFrame, RLBotError>> {
+        std::iter::from_fn(move || self.next_frame().transpose())
+    }
+}
+
+impl<R: Read> Iterator for SessionReader<R> {
+    type Item = Result<RecordedPacket, RLBotError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = match self.next_frame() {
+            Ok(frame) => frame?,
+            Err(e) => return Some(Err(e)),
+        };
+
+        Some(frame.to_packet().map(|packet| RecordedPacket {
+            direction: frame.direction,
+            timestamp: frame.timestamp,
+            packet,
+        }))
+    }
+}
@@ -0,0 +1,385 @@
+//! Driving agents, hiveminds and scripts with a recorded session instead of a live connection.
+//!
+//! The same callbacks as the runners are called, as if with [`FramePolicy::EveryPacket`]:
+//! every recorded game packet gets a tick since there's no real time to keep up with.
+
+use std::mem;
+
+use crate::{
+    ClientPacket, Packet, PacketParseError, RLBotError, ServerPacket, StartingInfo,
+    StartingInfoCollector,
+    agents::{Agent, start_agents},
+    context::{AgentContext, HivemindContext, ScriptContext, TickPacket},
+    drain::Frames,
+    flat::{
+        BallPrediction, ControllableTeamInfo, FieldInfo, GamePacket, MatchComm, MatchConfiguration,
+    },
+    hivemind::Hivemind,
+    recording::{Direction, RecordedPacket},
+    scripts::Script,
+    util::{FramePolicy, LastPackets, PacketQueue},
+};
+
+/// Everything the replayed bot queued
+#[derive(Debug, Default, Clone)]
+pub struct ReplayOutput {
+    /// Packets queued while creating the bot(s)
+    pub init: Vec<ClientPacket>,
+    /// Packets queued for each replayed game packet, in order
+    pub ticks: Vec<Vec<ClientPacket>>,
+}
+
+/// Callbacks shared by agents, hiveminds and scripts
+trait Replayable {
+    fn on_match_comm(&mut self, match_comm: &MatchComm, packet_queue: &mut PacketQueue);
+    fn on_ball_prediction(&mut self, ball_prediction: &BallPrediction);
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue);
+    fn on_match_configuration(
+        &mut self,
+        match_configuration: &MatchConfiguration,
+        packet_queue: &mut PacketQueue,
+    );
+    fn on_field_info(&mut self, field_info: &FieldInfo, packet_queue: &mut PacketQueue);
+    fn on_controllable_team_info(
+        &mut self,
+        controllable_team_info: &ControllableTeamInfo,
+        packet_queue: &mut PacketQueue,
+    );
+}
+
+struct Session<I> {
+    agent_id: Option<String>,
+    starting_info: StartingInfo,
+    /// Packets received after the starting info
+    remaining: I,
+}
+
+/// The server packet in `recorded`, `None` if the bot sent it
+fn received(recorded: RecordedPacket) -> Option<Result<ServerPacket, RLBotError>> {
+    (recorded.direction == Direction::Received).then(|| {
+        ServerPacket::try_from(recorded.packet)
+            .map_err(|packet| PacketParseError::WrongDirection(packet.data_type()).into())
+    })
+}
+
+/// Finds the starting info and the agent id the bot connected with
+fn start_session(
+    session: impl IntoIterator<Item = Result<RecordedPacket, RLBotError>>,
+) -> Result<Session<impl Iterator<Item = Result<ServerPacket, RLBotError>>>, RLBotError> {
+    let mut session = session.into_iter();
+
+    let mut agent_id = None;
+    let mut collector = StartingInfoCollector::default();
+    let starting_info = loop {
+        let Some(recorded) = session.next() else {
+            return Err(RLBotError::InvalidRecording("session has no starting info"));
+        };
+        let recorded = recorded?;
+
+        if let Packet::ConnectionSettings(x) = &recorded.packet {
+            agent_id = Some(x.agent_id.clone());
+        }
+
+        if let Some(packet) = received(recorded).transpose()?
+            && let Some(starting_info) = collector.add(packet)
+        {
+            break starting_info;
+        }
+    };
+
+    Ok(Session {
+        agent_id,
+        starting_info,
+        remaining: session.filter_map(|recorded| match recorded {
+            Ok(recorded) => received(recorded),
+            Err(e) => Some(Err(e)),
+        }),
+    })
+}
+
+/// Passes `frames` on the same way the runners do, with one output per tick
+fn replay_frames(
+    bot: &mut impl Replayable,
+    frames: Frames<BallPrediction, GamePacket>,
+    last_packets: &mut LastPackets,
+    packet_queue: &mut PacketQueue,
+    output: &mut ReplayOutput,
+) {
+    for frame in frames.finish() {
+        for match_comm in &frame.comms {
+            bot.on_match_comm(match_comm, packet_queue);
+        }
+
+        if let Some(ball_prediction) = frame.ball_prediction {
+            bot.on_ball_prediction(last_packets.ball_prediction(ball_prediction));
+        }
+
+        if let Some(game_packet) = frame.game_packet {
+            last_packets.tick(game_packet, |tick| bot.tick(tick, packet_queue));
+            output.ticks.push(packet_queue.empty());
+        }
+    }
+}
+
+/// Feeds the received packets to `bot` the same way the runners do
+/// with [`FramePolicy::EveryPacket`]
+fn replay(
+    bot: &mut impl Replayable,
+    mut packet_queue: PacketQueue,
+    packets: impl Iterator<Item = Result<ServerPacket, RLBotError>>,
+) -> Result<ReplayOutput, RLBotError> {
+    let mut output = ReplayOutput {
+        init: packet_queue.empty(),
+        ticks: Vec::new(),
+    };
+
+    let mut last_packets = LastPackets::default();
+    let mut frames = Frames::new(FramePolicy::EveryPacket);
+    for packet in packets {
+        match packet? {
+            ServerPacket::None => break,
+            ServerPacket::MatchComm(match_comm) => frames.push_comm(match_comm),
+            ServerPacket::BallPrediction(ball_pred) => frames.push_ball_prediction(ball_pred),
+            ServerPacket::GamePacket(game_packet) => {
+                // every game packet completes a frame, so it's passed on right away
+                frames.push_game_packet(game_packet);
+                let done = mem::replace(&mut frames, Frames::new(FramePolicy::EveryPacket));
+                replay_frames(bot, done, &mut last_packets, &mut packet_queue, &mut output);
+            }
+            ServerPacket::MatchConfiguration(x) => {
+                bot.on_match_configuration(&x, &mut packet_queue)
+            }
+            ServerPacket::FieldInfo(x) => bot.on_field_info(&x, &mut packet_queue),
+            ServerPacket::ControllableTeamInfo(x) => {
+                bot.on_controllable_team_info(&x, &mut packet_queue)
+            }
+        }
+    }
+
+    // comms and a ball prediction received after the last game packet
+    replay_frames(
+        bot,
+        frames,
+        &mut last_packets,
+        &mut packet_queue,
+        &mut output,
+    );
+
+    Ok(output)
+}
+
+/// Agents with a queue each like in the runners, so what each agent sends stays together.
+/// The queues are only moved to the shared one after a tick, when the runners send them.
+struct AgentsReplay<T> {
+    agents: Vec<T>,
+    queues: Vec<PacketQueue>,
+}
+
+impl<T> AgentsReplay<T> {
+    /// Moves what the agents queued to `packet_queue`, one agent after the other
+    fn flush(&mut self, packet_queue: &mut PacketQueue) {
+        for queue in &mut self.queues {
+            for packet in queue.empty() {
+                packet_queue.push(packet);
+            }
+        }
+    }
+
+    fn for_each(&mut self, mut f: impl FnMut(&mut T, &mut PacketQueue)) {
+        for (agent, packet_queue) in self.agents.iter_mut().zip(&mut self.queues) {
+            f(agent, packet_queue);
+        }
+    }
+}
+
+impl<T: Agent> Replayable for AgentsReplay<T> {
+    fn on_match_comm(&mut self, match_comm: &MatchComm, _packet_queue: &mut PacketQueue) {
+        self.for_each(|agent, packet_queue| agent.on_match_comm(match_comm, packet_queue));
+    }
+
+    fn on_ball_prediction(&mut self, ball_prediction: &BallPrediction) {
+        for agent in &mut self.agents {
+            agent.on_ball_prediction(ball_prediction);
+        }
+    }
+
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        self.for_each(|agent, packet_queue| agent.tick(game_packet, packet_queue));
+        self.flush(packet_queue);
+    }
+
+    fn on_match_configuration(
+        &mut self,
+        match_configuration: &MatchConfiguration,
+        _packet_queue: &mut PacketQueue,
+    ) {
+        self.for_each(|agent, packet_queue| {
+            agent.on_match_configuration(match_configuration, packet_queue);
+        });
+    }
+
+    fn on_field_info(&mut self, field_info: &FieldInfo, _packet_queue: &mut PacketQueue) {
+        self.for_each(|agent, packet_queue| agent.on_field_info(field_info, packet_queue));
+    }
+
+    fn on_controllable_team_info(
+        &mut self,
+        controllable_team_info: &ControllableTeamInfo,
+        _packet_queue: &mut PacketQueue,
+    ) {
+        self.for_each(|agent, packet_queue| {
+            agent.on_controllable_team_info(controllable_team_info, packet_queue);
+        });
+    }
+}
+
+struct HivemindReplay<T>(T);
+
+impl<T: Hivemind> Replayable for HivemindReplay<T> {
+    fn on_match_comm(&mut self, match_comm: &MatchComm, packet_queue: &mut PacketQueue) {
+        self.0.on_match_comm(match_comm, packet_queue);
+    }
+
+    fn on_ball_prediction(&mut self, ball_prediction: &BallPrediction) {
+        self.0.on_ball_prediction(ball_prediction);
+    }
+
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        self.0.tick(game_packet, packet_queue);
+    }
+
+    fn on_match_configuration(
+        &mut self,
+        match_configuration: &MatchConfiguration,
+        packet_queue: &mut PacketQueue,
+    ) {
+        self.0
+            .on_match_configuration(match_configuration, packet_queue);
+    }
+
+    fn on_field_info(&mut self, field_info: &FieldInfo, packet_queue: &mut PacketQueue) {
+        self.0.on_field_info(field_info, packet_queue);
+    }
+
+    fn on_controllable_team_info(
+        &mut self,
+        controllable_team_info: &ControllableTeamInfo,
+        packet_queue: &mut PacketQueue,
+    ) {
+        self.0
+            .on_controllable_team_info(controllable_team_info, packet_queue);
+    }
+}
+
+struct ScriptReplay<T>(T);
+
+impl<T: Script> Replayable for ScriptReplay<T> {
+    fn on_match_comm(&mut self, match_comm: &MatchComm, packet_queue: &mut PacketQueue) {
+        self.0.on_match_comm(match_comm, packet_queue);
+    }
+
+    fn on_ball_prediction(&mut self, ball_prediction: &BallPrediction) {
+        self.0.on_ball_prediction(ball_prediction);
+    }
+
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        self.0.tick(game_packet, packet_queue);
+    }
+
+    fn on_match_configuration(
+        &mut self,
+        match_configuration: &MatchConfiguration,
+        packet_queue: &mut PacketQueue,
+    ) {
+        self.0
+            .on_match_configuration(match_configuration, packet_queue);
+    }
+
+    fn on_field_info(&mut self, field_info: &FieldInfo, packet_queue: &mut PacketQueue) {
+        self.0.on_field_info(field_info, packet_queue);
+    }
+
+    fn on_controllable_team_info(
+        &mut self,
+        controllable_team_info: &ControllableTeamInfo,
+        packet_queue: &mut PacketQueue,
+    ) {
+        self.0
+            .on_controllable_team_info(controllable_team_info, packet_queue);
+    }
+}
+
+/// Replays a recorded session, e.g. from a [`SessionReader`](crate::recording::SessionReader),
+/// with one `T` per controllable in the recorded [`ControllableTeamInfo`].
+/// Like in the runners, each tick's output has everything one agent queued before the next agent's.
+///
+/// # Errors
+///
+/// Returns an error if the session can't be read or has no starting info.
+pub fn replay_agents<T: Agent>(
+    session: impl IntoIterator<Item = Result<RecordedPacket, RLBotError>>,
+) -> Result<ReplayOutput, RLBotError> {
+    let Session {
+        starting_info,
+        remaining,
+        ..
+    } = start_session(session)?;
+
+    let mut agents = AgentsReplay {
+        agents: Vec::new(),
+        queues: Vec::new(),
+    };
+    start_agents::<T>(
+        &mut agents.agents,
+        &mut agents.queues,
+        AgentContext::from_starting_info(starting_info),
+    );
+
+    let mut packet_queue = PacketQueue::default();
+    agents.flush(&mut packet_queue);
+
+    replay(&mut agents, packet_queue, remaining)
+}
+
+/// Replays a recorded session with a single `T` controlling every recorded controllable.
+///
+/// # Errors
+///
+/// Returns an error if the session can't be read or has no starting info.
+pub fn replay_hivemind<T: Hivemind>(
+    session: impl IntoIterator<Item = Result<RecordedPacket, RLBotError>>,
+) -> Result<ReplayOutput, RLBotError> {
+    let Session {
+        starting_info,
+        remaining,
+        ..
+    } = start_session(session)?;
+
+    let mut packet_queue = PacketQueue::default();
+    let hivemind = T::new(HivemindContext::from(starting_info), &mut packet_queue);
+
+    replay(&mut HivemindReplay(hivemind), packet_queue, remaining)
+}
+
+/// Replays a recorded session with a script, using the agent id the recorded bot connected with.
+///
+/// # Errors
+///
+/// Returns an error if the session can't be read or has no starting info,
+/// or [`RLBotError::MissingAgentId`] if the recorded bot never sent its agent id.
+pub fn replay_script<T: Script>(
+    session: impl IntoIterator<Item = Result<RecordedPacket, RLBotError>>,
+) -> Result<ReplayOutput, RLBotError> {
+    let Session {
+        agent_id,
+        starting_info,
+        remaining,
+    } = start_session(session)?;
+
+    let agent_id = agent_id.ok_or(RLBotError::MissingAgentId)?;
+    let context = ScriptContext::from_starting_info(agent_id, starting_info);
+    let mut packet_queue = PacketQueue::default();
+    let script = T::new(context, &mut packet_queue);
+
+    replay(&mut ScriptReplay(script), packet_queue, remaining)
+}
@@ -0,0 +1,408 @@
+//! A builder for running agents, hiveminds and scripts,
+//! so new options don't change the signature of every runner.
+//!
+//! ```no_run
+//! # use rlbot::{agents::Agent, runner::Runner};
+//! # fn run<MyAgent: Agent>() {
+//! Runner::agents()
+//!     .fallback_agent_id("me/my_bot")
+//!     .wants_ball_predictions(true)
+//!     .run::<MyAgent>()
+//!     .expect("run_agents crashed");
+//! # }
+//! ```
+
+use std::{
+    marker::PhantomData,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+#[cfg(feature = "tokio")]
+use crate::asynchronous::{self, AsyncRLBotConnection};
+use crate::{
+    RLBotConnection, RLBotError,
+    agents::{
+        self, Agent, AgentError, AgentOptions, AgentPanicPolicy, AgentScheduling, TickDeadline,
+        ZeroCopyAgent,
+    },
+    connect::ConnectOptions,
+    flat::ConnectionSettings,
+    hivemind::{self, Hivemind, HivemindError, ZeroCopyHivemind},
+    scripts::{self, Script, ScriptError},
+    transport::Transport,
+    util::{FramePolicy, InputPolicy, RLBotEnvironment, UnexpectedPacketPolicy},
+};
+
+/// Everything a runner needs besides the connection
+#[derive(Debug, Clone)]
+pub(crate) struct RunnerConfig {
+    pub connection_settings: ConnectionSettings,
+    pub unexpected_packets: UnexpectedPacketPolicy,
+    pub input_policy: InputPolicy,
+    pub frame_policy: FramePolicy,
+    pub runner_stats: Arc<RunnerStats>,
+}
+
+impl RunnerConfig {
+    pub fn new(agent_id: String, wants_ball_predictions: bool, wants_comms: bool) -> Self {
+        Self {
+            connection_settings: ConnectionSettings {
+                agent_id,
+                wants_ball_predictions,
+                wants_comms,
+                close_between_matches: true,
+            },
+            unexpected_packets: UnexpectedPacketPolicy::default(),
+            input_policy: InputPolicy::default(),
+            frame_policy: FramePolicy::default(),
+            runner_stats: Arc::default(),
+        }
+    }
+}
+
+/// Counters about a run, see [`Runner::stats`]
+#[derive(Debug, Default)]
+pub struct RunnerStats {
+    agent_panics: AtomicU64,
+    dropped_comms: AtomicU64,
+    input_violations: AtomicU64,
+}
+
+impl RunnerStats {
+    /// How many times any agent panicked, see [`AgentPanicPolicy`]
+    pub fn agent_panics(&self) -> u64 {
+        self.agent_panics.load(Ordering::Relaxed)
+    }
+
+    /// How many [`MatchComm`](crate::flat::MatchComm)s agent threads missed
+    /// because they fell too far behind
+    pub fn dropped_comms(&self) -> u64 {
+        self.dropped_comms.load(Ordering::Relaxed)
+    }
+
+    /// How many [`PlayerInput`](crate::flat::PlayerInput)s broke the [`InputPolicy`]
+    /// and were sent anyway because it's [`InputPolicy::Warn`]
+    pub fn input_violations(&self) -> u64 {
+        self.input_violations.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_agent_panic(&self) {
+        self.agent_panics.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_dropped_comms(&self, dropped: u64) {
+        self.dropped_comms.fetch_add(dropped, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_input_violation(&self) {
+        self.input_violations.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Marks a [`Runner`] for [`Agent`]s, see [`Runner::agents`]
+pub struct AgentsKind;
+/// Marks a [`Runner`] for a [`Hivemind`], see [`Runner::hivemind`]
+pub struct HivemindKind;
+/// Marks a [`Runner`] for a [`Script`], see [`Runner::script`]
+pub struct ScriptKind;
+
+/// Builds the settings for running agents, a hivemind or a script,
+/// finished by calling `run::<T>()`
+pub struct Runner<K> {
+    kind: PhantomData<K>,
+    server_addr: String,
+    agent_id: Option<String>,
+    wants_ball_predictions: bool,
+    wants_comms: bool,
+    close_between_matches: bool,
+    unexpected_packets: UnexpectedPacketPolicy,
+    input_policy: InputPolicy,
+    frame_policy: FramePolicy,
+    connect_options: ConnectOptions,
+    agent_options: AgentOptions,
+    runner_stats: Arc<RunnerStats>,
+}
+
+impl Runner<AgentsKind> {
+    /// Runs one [`Agent`] per controllable, configured from the environment
+    #[must_use]
+    pub fn agents() -> Self {
+        Self::from_environment(RLBotEnvironment::from_env())
+    }
+}
+
+impl Runner<HivemindKind> {
+    /// Runs a single [`Hivemind`] for all controllables, configured from the environment
+    #[must_use]
+    pub fn hivemind() -> Self {
+        Self::from_environment(RLBotEnvironment::from_env())
+    }
+}
+
+impl Runner<ScriptKind> {
+    /// Runs a [`Script`], configured from the environment
+    #[must_use]
+    pub fn script() -> Self {
+        Self::from_environment(RLBotEnvironment::from_env())
+    }
+}
+
+impl<K> Runner<K> {
+    #[must_use]
+    pub fn from_environment(environment: RLBotEnvironment) -> Self {
+        Self {
+            kind: PhantomData,
+            server_addr: environment.server_addr,
+            agent_id: environment.agent_id,
+            wants_ball_predictions: false,
+            wants_comms: false,
+            close_between_matches: true,
+            unexpected_packets: UnexpectedPacketPolicy::default(),
+            input_policy: InputPolicy::default(),
+            frame_policy: FramePolicy::default(),
+            connect_options: ConnectOptions::default(),
+            agent_options: AgentOptions::default(),
+            runner_stats: Arc::default(),
+        }
+    }
+
+    /// Overrides the server address from the environment
+    #[must_use]
+    pub fn server_addr(mut self, server_addr: impl Into<String>) -> Self {
+        self.server_addr = server_addr.into();
+        self
+    }
+
+    /// Overrides the agent id from the environment
+    #[must_use]
+    pub fn agent_id(mut self, agent_id: impl Into<String>) -> Self {
+        self.agent_id = Some(agent_id.into());
+        self
+    }
+
+    /// Agent id to use if none was set in the environment, e.g. when starting the bot manually
+    #[must_use]
+    pub fn fallback_agent_id(mut self, agent_id: impl Into<String>) -> Self {
+        self.agent_id.get_or_insert_with(|| agent_id.into());
+        self
+    }
+
+    #[must_use]
+    pub fn wants_ball_predictions(mut self, wants_ball_predictions: bool) -> Self {
+        self.wants_ball_predictions = wants_ball_predictions;
+        self
+    }
+
+    #[must_use]
+    pub fn wants_comms(mut self, wants_comms: bool) -> Self {
+        self.wants_comms = wants_comms;
+        self
+    }
+
+    /// Defaults to true, see [`Agent::on_match_start`] for what happens when it's false
+    #[must_use]
+    pub fn close_between_matches(mut self, close_between_matches: bool) -> Self {
+        self.close_between_matches = close_between_matches;
+        self
+    }
+
+    #[must_use]
+    pub fn unexpected_packets(mut self, policy: UnexpectedPacketPolicy) -> Self {
+        self.unexpected_packets = policy;
+        self
+    }
+
+    /// Defaults to [`InputPolicy::Forward`]. [`Agent`]s are only allowed inputs for their own car.
+    #[must_use]
+    pub fn input_policy(mut self, policy: InputPolicy) -> Self {
+        self.input_policy = policy;
+        self
+    }
+
+    /// Defaults to [`FramePolicy::LatestOnly`]
+    #[must_use]
+    pub fn frame_policy(mut self, policy: FramePolicy) -> Self {
+        self.frame_policy = policy;
+        self
+    }
+
+    /// Used when the runner connects by itself, see [`Self::connect`]
+    #[must_use]
+    pub fn connect_options(mut self, connect_options: ConnectOptions) -> Self {
+        self.connect_options = connect_options;
+        self
+    }
+
+    /// The agent id the runner will connect with, if there is one
+    #[must_use]
+    pub fn get_agent_id(&self) -> Option<&str> {
+        self.agent_id.as_deref()
+    }
+
+    /// Counters that are updated while the bot runs, get them before calling `run`
+    #[must_use]
+    pub fn stats(&self) -> Arc<RunnerStats> {
+        self.runner_stats.clone()
+    }
+
+    /// Connects to the server address with the configured [`ConnectOptions`]
+    pub fn connect(&self) -> Result<RLBotConnection, RLBotError> {
+        RLBotConnection::connect(&self.server_addr, &self.connect_options)
+    }
+
+    fn config(&self) -> Result<RunnerConfig, RLBotError> {
+        let agent_id = self.agent_id.clone().ok_or(RLBotError::MissingAgentId)?;
+
+        Ok(RunnerConfig {
+            connection_settings: ConnectionSettings {
+                agent_id,
+                wants_ball_predictions: self.wants_ball_predictions,
+                wants_comms: self.wants_comms,
+                close_between_matches: self.close_between_matches,
+            },
+            unexpected_packets: self.unexpected_packets,
+            input_policy: self.input_policy,
+            frame_policy: self.frame_policy,
+            runner_stats: self.runner_stats.clone(),
+        })
+    }
+}
+
+impl Runner<AgentsKind> {
+    /// Defaults to [`AgentScheduling::ThreadPerAgent`].
+    /// Ignored by [`Self::run_zero_copy`] and [`Self::run_async`], which run all agents on the calling thread.
+    #[must_use]
+    pub fn scheduling(mut self, scheduling: AgentScheduling) -> Self {
+        self.agent_options.scheduling = scheduling;
+        self
+    }
+
+    /// Stops waiting for slow agents after the deadline, defaults to waiting for all of them.
+    /// Has no effect with [`AgentScheduling::CallingThread`], [`Self::run_zero_copy`] and [`Self::run_async`].
+    #[must_use]
+    pub fn tick_deadline(mut self, tick_deadline: TickDeadline) -> Self {
+        self.agent_options.tick_deadline = Some(tick_deadline);
+        self
+    }
+
+    /// Defaults to [`AgentPanicPolicy::Propagate`].
+    /// The other policies need panics to unwind, so a panicking agent still aborts the process
+    /// when built with `panic = "abort"`. Ignored by [`Self::run_zero_copy`], where panics always propagate.
+    #[must_use]
+    pub fn on_agent_panic(mut self, policy: AgentPanicPolicy) -> Self {
+        self.agent_options.on_panic = policy;
+        self
+    }
+
+    /// Connects and blocks until the server sends [`ServerPacket::None`](crate::ServerPacket::None)
+    ///
+    /// # Errors
+    ///
+    /// See [`agents::run_agents`]
+    pub fn run<T: Agent>(self) -> Result<(), AgentError> {
+        let config = self.config()?;
+        let connection = self.connect()?;
+        agents::run_agents_with::<T>(config, self.agent_options, connection)
+    }
+
+    /// Like [`Self::run`], but over an already established connection
+    pub fn run_with_connection<T: Agent>(
+        self,
+        connection: RLBotConnection<impl Transport>,
+    ) -> Result<(), AgentError> {
+        agents::run_agents_with::<T>(self.config()?, self.agent_options, connection)
+    }
+
+    /// Like [`Self::run_with_connection`], for [`ZeroCopyAgent`]s.
+    /// Packets are borrowed from the receive buffer, so all agents run on the calling thread
+    /// and only the settings shared with hiveminds and scripts apply.
+    pub fn run_zero_copy<T: ZeroCopyAgent>(
+        self,
+        connection: RLBotConnection<impl Transport>,
+    ) -> Result<(), AgentError> {
+        agents::run_zero_copy_agents_with::<T>(self.config()?, connection)
+    }
+
+    /// Like [`Self::run_with_connection`], but async. All agents run on the calling task
+    /// and block it while they tick, see [`asynchronous::run_agents`].
+    #[cfg(feature = "tokio")]
+    pub async fn run_async<T: Agent>(
+        self,
+        connection: AsyncRLBotConnection,
+    ) -> Result<(), AgentError> {
+        asynchronous::run_agents_with::<T>(self.config()?, self.agent_options, connection).await
+    }
+}
+
+impl Runner<HivemindKind> {
+    /// Connects and blocks until the server sends [`ServerPacket::None`](crate::ServerPacket::None)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails or an unexpected packet is received
+    /// with [`UnexpectedPacketPolicy::Error`].
+    pub fn run<T: Hivemind>(self) -> Result<(), HivemindError> {
+        let config = self.config()?;
+        hivemind::run_hivemind_with::<T>(config, self.connect()?)
+    }
+
+    /// Like [`Self::run`], but over an already established connection
+    pub fn run_with_connection<T: Hivemind>(
+        self,
+        connection: RLBotConnection<impl Transport>,
+    ) -> Result<(), HivemindError> {
+        hivemind::run_hivemind_with::<T>(self.config()?, connection)
+    }
+
+    /// Like [`Self::run_with_connection`], for a [`ZeroCopyHivemind`]
+    pub fn run_zero_copy<T: ZeroCopyHivemind>(
+        self,
+        connection: RLBotConnection<impl Transport>,
+    ) -> Result<(), HivemindError> {
+        hivemind::run_zero_copy_hivemind_with::<T>(self.config()?, connection)
+    }
+
+    /// Like [`Self::run_with_connection`], but async. The hivemind blocks the calling task
+    /// while it ticks, see [`asynchronous::run_hivemind`].
+    #[cfg(feature = "tokio")]
+    pub async fn run_async<T: Hivemind>(
+        self,
+        connection: AsyncRLBotConnection,
+    ) -> Result<(), HivemindError> {
+        asynchronous::run_hivemind_with::<T>(self.config()?, connection).await
+    }
+}
+
+impl Runner<ScriptKind> {
+    /// Connects and blocks until the server sends [`ServerPacket::None`](crate::ServerPacket::None)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails or an unexpected packet is received
+    /// with [`UnexpectedPacketPolicy::Error`].
+    pub fn run<T: Script>(self) -> Result<(), ScriptError> {
+        let config = self.config()?;
+        scripts::run_script_with::<T>(config, self.connect()?)
+    }
+
+    /// Like [`Self::run`], but over an already established connection
+    pub fn run_with_connection<T: Script>(
+        self,
+        connection: RLBotConnection<impl Transport>,
+    ) -> Result<(), ScriptError> {
+        scripts::run_script_with::<T>(self.config()?, connection)
+    }
+
+    /// Like [`Self::run_with_connection`], but async. The script blocks the calling task
+    /// while it ticks, see [`asynchronous::run_script`].
+    #[cfg(feature = "tokio")]
+    pub async fn run_async<T: Script>(
+        self,
+        connection: AsyncRLBotConnection,
+    ) -> Result<(), ScriptError> {
+        asynchronous::run_script_with::<T>(self.config()?, connection).await
+    }
+}
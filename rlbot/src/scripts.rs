@@ -1,90 +1,143 @@
 use rlbot_flat::flat::{
-    BallPrediction, ConnectionSettings, FieldInfo, GamePacket, MatchComm, MatchConfiguration,
+    BallPrediction, ControllableTeamInfo, FieldInfo, MatchComm, MatchConfiguration,
 };
 
 use crate::{
-    Packet, RLBotConnection, StartingInfo,
-    util::{PacketQueue, write_multiple_packets},
+    RLBotConnection, StartingInfo,
+    context::{ScriptContext, TickPacket},
+    dispatch::{self, Bot},
+    runner::RunnerConfig,
+    transport::Transport,
+    util::PacketQueue,
 };
 
 #[allow(unused_variables)]
 pub trait Script {
-    fn new(
-        agent_id: String,
-        match_configuration: MatchConfiguration,
-        field_info: FieldInfo,
+    fn new(context: ScriptContext, packet_queue: &mut PacketQueue) -> Self;
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue);
+    fn on_match_comm(&mut self, match_comm: &MatchComm, packet_queue: &mut PacketQueue) {}
+    fn on_ball_prediction(&mut self, ball_prediction: &BallPrediction) {}
+    fn on_match_configuration(
+        &mut self,
+        match_configuration: &MatchConfiguration,
+        packet_queue: &mut PacketQueue,
+    ) {
+    }
+    fn on_field_info(&mut self, field_info: &FieldInfo, packet_queue: &mut PacketQueue) {}
+    fn on_controllable_team_info(
+        &mut self,
+        controllable_team_info: &ControllableTeamInfo,
         packet_queue: &mut PacketQueue,
-    ) -> Self;
-    fn tick(&mut self, game_packet: GamePacket, packet_queue: &mut PacketQueue);
-    fn on_match_comm(&mut self, match_comm: MatchComm, packet_queue: &mut PacketQueue) {}
-    fn on_ball_prediction(&mut self, ball_prediction: BallPrediction) {}
+    ) {
+    }
+    /// Called instead of creating a new script when a new match starts
+    /// and `close_between_matches` is false. Rebuilds the script with [`Self::new`] by default.
+    fn on_match_start(&mut self, context: ScriptContext, packet_queue: &mut PacketQueue)
+    where
+        Self: Sized,
+    {
+        *self = Self::new(context, packet_queue);
+    }
+    /// Called when the match ends, before the next one starts or the runner exits
+    fn on_match_end(&mut self) {}
 }
 
 #[derive(thiserror::Error, Debug)]
 pub enum ScriptError {
-    #[error("Script panicked")]
-    ScriptPanic,
     #[error("RLBot failed")]
     PacketParseError(#[from] crate::RLBotError),
 }
 
+/// Shorthand for [`Runner::script`](crate::runner::Runner::script), which has more options
 pub fn run_script<T: Script>(
     agent_id: String,
     wants_ball_predictions: bool,
     wants_comms: bool,
-    mut connection: RLBotConnection,
+    connection: RLBotConnection<impl Transport>,
 ) -> Result<(), ScriptError> {
-    connection.send_packet(ConnectionSettings {
-        agent_id: agent_id.clone(),
-        wants_ball_predictions,
-        wants_comms,
-        close_between_matches: true,
-    })?;
-
-    let StartingInfo {
-        controllable_team_info: _,
-        match_configuration,
-        field_info,
-    } = connection.get_starting_info()?;
-
-    let mut outgoing_queue = PacketQueue::default();
-    let mut script = T::new(
-        agent_id,
-        match_configuration,
-        field_info,
-        &mut outgoing_queue,
-    );
-
-    outgoing_queue.push(Packet::InitComplete);
-    write_multiple_packets(&mut connection, outgoing_queue.empty().into_iter())?;
-
-    let mut ball_prediction = None;
-    let mut game_packet = None;
-    'main_loop: loop {
-        connection.set_nonblocking(true)?;
-        while let Ok(packet) = connection.recv_packet() {
-            match packet {
-                Packet::None => break 'main_loop,
-                Packet::MatchComm(match_comm) => {
-                    script.on_match_comm(match_comm, &mut outgoing_queue);
-                }
-                Packet::BallPrediction(ball_pred) => ball_prediction = Some(ball_pred),
-                Packet::GamePacket(gp) => game_packet = Some(gp),
-                _ => panic!("Unexpected packet: {packet:?}"),
-            }
-        }
-        connection.set_nonblocking(false)?;
+    run_script_with::<T>(
+        RunnerConfig::new(agent_id, wants_ball_predictions, wants_comms),
+        connection,
+    )
+}
 
-        if let Some(game_packet) = game_packet.take() {
-            if let Some(ball_prediction) = ball_prediction.take() {
-                script.on_ball_prediction(ball_prediction);
-            }
+pub(crate) fn run_script_with<T: Script>(
+    config: RunnerConfig,
+    connection: RLBotConnection<impl Transport>,
+) -> Result<(), ScriptError> {
+    let agent_id = config.connection_settings.agent_id.clone();
+    Ok(dispatch::run(
+        &config,
+        connection,
+        |starting_info, packet_queue| ScriptBot::<T>::new(agent_id, starting_info, packet_queue),
+    )?)
+}
 
-            script.tick(game_packet, &mut outgoing_queue);
+/// A [`Script`] as the runners see it, with the agent id for its next [`ScriptContext`]s
+pub(crate) struct ScriptBot<T> {
+    script: T,
+    agent_id: String,
+}
+
+impl<T: Script> ScriptBot<T> {
+    pub fn new(
+        agent_id: String,
+        starting_info: StartingInfo,
+        packet_queue: &mut PacketQueue,
+    ) -> Self {
+        let context = ScriptContext::from_starting_info(agent_id.clone(), starting_info);
 
-            write_multiple_packets(&mut connection, outgoing_queue.empty().into_iter())?;
+        Self {
+            script: T::new(context, packet_queue),
+            agent_id,
         }
     }
+}
 
-    Ok(())
+impl<T: Script> Bot for ScriptBot<T> {
+    const CONTROLS_CARS: bool = false;
+
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        self.script.tick(game_packet, packet_queue);
+    }
+
+    fn on_match_comm(&mut self, match_comm: &MatchComm, packet_queue: &mut PacketQueue) {
+        self.script.on_match_comm(match_comm, packet_queue);
+    }
+
+    fn on_ball_prediction(&mut self, ball_prediction: &BallPrediction) {
+        self.script.on_ball_prediction(ball_prediction);
+    }
+
+    fn on_match_configuration(
+        &mut self,
+        match_configuration: &MatchConfiguration,
+        packet_queue: &mut PacketQueue,
+    ) {
+        self.script
+            .on_match_configuration(match_configuration, packet_queue);
+    }
+
+    fn on_field_info(&mut self, field_info: &FieldInfo, packet_queue: &mut PacketQueue) {
+        self.script.on_field_info(field_info, packet_queue);
+    }
+
+    fn on_controllable_team_info(
+        &mut self,
+        controllable_team_info: &ControllableTeamInfo,
+        packet_queue: &mut PacketQueue,
+    ) {
+        self.script
+            .on_controllable_team_info(controllable_team_info, packet_queue);
+    }
+
+    fn restart(&mut self, starting_info: StartingInfo, packet_queue: &mut PacketQueue) {
+        let context = ScriptContext::from_starting_info(self.agent_id.clone(), starting_info);
+        self.script.on_match_end();
+        self.script.on_match_start(context, packet_queue);
+    }
+
+    fn on_match_end(&mut self) {
+        self.script.on_match_end();
+    }
 }
@@ -0,0 +1,133 @@
+//! Independent receiving and sending halves of an [`RLBotConnection`],
+//! created with [`RLBotConnection::split`].
+
+use std::{error::Error, fmt, net::TcpStream, ops::Range, sync::Arc};
+
+use crate::{
+    ClientPacket, Packet, PacketRef, RLBotConnection, RLBotError, ServerPacket,
+    connect::map_timeout,
+    framing::{FrameDecoder, FrameEncoder},
+    transport::Transport,
+};
+
+/// The receiving half of a split [`RLBotConnection`].
+/// Reads always block, the stream is shared with the [`PacketWriter`] which needs it in blocking mode.
+pub struct PacketReader<S: Transport = TcpStream> {
+    stream: S,
+    recv_buf: FrameDecoder,
+    id: Arc<()>,
+}
+
+/// The sending half of a split [`RLBotConnection`]
+pub struct PacketWriter<S: Transport = TcpStream> {
+    stream: S,
+    encoder: FrameEncoder,
+    id: Arc<()>,
+}
+
+pub(crate) fn new<S: Transport>(
+    read_stream: S,
+    recv_buf: FrameDecoder,
+    write_stream: S,
+    encoder: FrameEncoder,
+) -> (PacketReader<S>, PacketWriter<S>) {
+    let id = Arc::new(());
+
+    (
+        PacketReader {
+            stream: read_stream,
+            recv_buf,
+            id: id.clone(),
+        },
+        PacketWriter {
+            stream: write_stream,
+            encoder,
+            id,
+        },
+    )
+}
+
+impl<S: Transport> PacketReader<S> {
+    /// Blocks until a full packet has been received
+    pub fn recv_packet(&mut self) -> Result<ServerPacket, RLBotError> {
+        self.recv_buf.wait_for_frame(&mut self.stream)?;
+
+        Ok(self
+            .recv_buf
+            .next_packet()?
+            .expect("a full frame should be buffered"))
+    }
+
+    /// Like [`Self::recv_packet`], but game packets and ball predictions
+    /// are borrowed from the receive buffer instead of being converted.
+    pub fn recv_packet_ref(&mut self) -> Result<PacketRef<'_>, RLBotError> {
+        self.recv_buf.wait_for_frame(&mut self.stream)?;
+
+        let (data_type, payload) = self
+            .recv_buf
+            .next_frame()
+            .expect("a full frame should be buffered");
+
+        Ok(PacketRef::from_payload(data_type, payload)?)
+    }
+
+    /// Puts the halves back together, keeping any packets that were buffered but not received yet
+    ///
+    /// # Errors
+    ///
+    /// Returns both halves if they weren't split from the same connection.
+    pub fn reunite(
+        self,
+        writer: PacketWriter<S>,
+    ) -> Result<RLBotConnection<S>, Box<ReuniteError<S>>> {
+        if !Arc::ptr_eq(&self.id, &writer.id) {
+            return Err(Box::new(ReuniteError(self, writer)));
+        }
+
+        let mut connection = RLBotConnection::from_transport(self.stream);
+        connection.recv_buf = self.recv_buf;
+        connection.encoder = writer.encoder;
+
+        Ok(connection)
+    }
+}
+
+impl<S: Transport> PacketWriter<S> {
+    pub fn send_packet(&mut self, packet: impl Into<ClientPacket>) -> Result<(), RLBotError> {
+        self.send_packets([packet.into()])
+    }
+
+    /// Encodes all packets first and then writes them at once
+    pub fn send_packets(
+        &mut self,
+        packets: impl IntoIterator<Item = ClientPacket>,
+    ) -> Result<(), RLBotError> {
+        let to_write = self.encoder.encode(packets.into_iter().map(Packet::from))?;
+
+        self.stream.write_all(to_write).map_err(map_timeout)?;
+        self.stream.flush().map_err(map_timeout)?;
+        Ok(())
+    }
+
+    /// See [`RLBotConnection::set_split_render_groups`]
+    pub fn set_split_render_groups(&mut self, extra_ids: Option<Range<i32>>) {
+        self.encoder.split_render_groups = extra_ids;
+    }
+}
+
+/// Returned by [`PacketReader::reunite`] when the halves belong to different connections
+pub struct ReuniteError<S: Transport>(pub PacketReader<S>, pub PacketWriter<S>);
+
+impl<S: Transport> fmt::Debug for ReuniteError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ReuniteError").finish_non_exhaustive()
+    }
+}
+
+impl<S: Transport> fmt::Display for ReuniteError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("tried to reunite halves of different connections")
+    }
+}
+
+impl<S: Transport> Error for ReuniteError<S> {}
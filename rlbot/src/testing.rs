@@ -0,0 +1,209 @@
+//! Utilities for testing agents, hiveminds and scripts without RLBotServer or the game running.
+
+use std::{
+    net::{SocketAddr, TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ClientPacket, Packet, RLBotConnection, RLBotError, ServerPacket, StartingInfo,
+    flat::{ConnectionSettings, DesiredGameState, PlayerInput, RenderGroup},
+    transport::{MemoryStream, Transport},
+};
+
+/// Accepts bots connecting over TCP, like RLBotServer does
+pub struct MockListener {
+    listener: TcpListener,
+}
+
+impl MockListener {
+    /// Use port 0 to let the OS pick a free port, see [`Self::local_addr`]
+    pub fn bind(addr: &str) -> Result<Self, RLBotError> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, RLBotError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Blocks until a bot connects
+    pub fn accept(&self) -> Result<MockServer<TcpStream>, RLBotError> {
+        let (stream, _) = self.listener.accept()?;
+        stream.set_nodelay(true)?;
+
+        Ok(MockServer::new(RLBotConnection::from_transport(stream)))
+    }
+}
+
+/// The server side of a single bot connection.
+/// Performs the same handshake as RLBotServer, sends scripted packets
+/// and records every packet the bot sends back.
+pub struct MockServer<S: Transport = TcpStream> {
+    connection: RLBotConnection<S>,
+    connection_settings: Option<ConnectionSettings>,
+    received: Vec<ClientPacket>,
+}
+
+impl MockServer<MemoryStream> {
+    /// Creates a server and a connected client connection that can be passed to a runner
+    #[must_use]
+    pub fn in_memory() -> (Self, RLBotConnection<MemoryStream>) {
+        let (server, client) = MemoryStream::pair();
+
+        (
+            Self::new(RLBotConnection::from_transport(server)),
+            RLBotConnection::from_transport(client),
+        )
+    }
+}
+
+impl<S: Transport> MockServer<S> {
+    /// Serves the bot on the other end of an already established stream
+    pub fn from_transport(stream: S) -> Self {
+        Self::new(RLBotConnection::from_transport(stream))
+    }
+
+    fn new(connection: RLBotConnection<S>) -> Self {
+        Self {
+            connection,
+            connection_settings: None,
+            received: Vec::new(),
+        }
+    }
+
+    /// Waits for [`ConnectionSettings`], sends the starting info
+    /// and then waits for the bot to send [`ClientPacket::InitComplete`]
+    pub fn handshake(
+        &mut self,
+        starting_info: StartingInfo,
+    ) -> Result<ConnectionSettings, RLBotError> {
+        let connection_settings = match self.connection_settings.clone() {
+            Some(connection_settings) => connection_settings,
+            None => loop {
+                if let ClientPacket::ConnectionSettings(x) = self.recv()? {
+                    break x;
+                }
+            },
+        };
+
+        self.send(starting_info.controllable_team_info)?;
+        self.send(starting_info.match_configuration)?;
+        self.send(starting_info.field_info)?;
+
+        while !matches!(self.recv()?, ClientPacket::InitComplete) {}
+
+        Ok(connection_settings)
+    }
+
+    /// The settings the bot connected with, if they have been received yet
+    #[must_use]
+    pub fn connection_settings(&self) -> Option<&ConnectionSettings> {
+        self.connection_settings.as_ref()
+    }
+
+    pub fn send(&mut self, packet: impl Into<ServerPacket>) -> Result<(), RLBotError> {
+        self.connection.write_packets([Packet::from(packet.into())])
+    }
+
+    /// Sends all `packets` in one write, so the bot receives them together
+    pub fn send_all(
+        &mut self,
+        packets: impl IntoIterator<Item = ServerPacket>,
+    ) -> Result<(), RLBotError> {
+        self.connection
+            .write_packets(packets.into_iter().map(Packet::from))
+    }
+
+    /// Blocks until the bot sends a packet, and records it
+    pub fn recv(&mut self) -> Result<ClientPacket, RLBotError> {
+        let packet = self.connection.read_packet::<ClientPacket>()?;
+        self.record(packet.clone());
+
+        Ok(packet)
+    }
+
+    /// Blocks until the bot sends a packet matching `predicate`, recording everything on the way
+    pub fn recv_until(
+        &mut self,
+        mut predicate: impl FnMut(&ClientPacket) -> bool,
+    ) -> Result<ClientPacket, RLBotError> {
+        loop {
+            let packet = self.recv()?;
+            if predicate(&packet) {
+                return Ok(packet);
+            }
+        }
+    }
+
+    /// Records everything the bot sends within `timeout`
+    pub fn recv_for(&mut self, timeout: Duration) -> Result<Vec<ClientPacket>, RLBotError> {
+        let deadline = Instant::now() + timeout;
+        let mut packets = Vec::new();
+
+        while Instant::now() < deadline {
+            match self.connection.try_read_packet::<ClientPacket>()? {
+                Some(packet) => {
+                    self.record(packet.clone());
+                    packets.push(packet);
+                }
+                None => thread::sleep(Duration::from_millis(1)),
+            }
+        }
+
+        Ok(packets)
+    }
+
+    /// Sends [`ServerPacket::None`] to end the session
+    /// and records everything the bot sends until it disconnects
+    pub fn finish(mut self) -> Result<Vec<ClientPacket>, RLBotError> {
+        self.send(ServerPacket::None)?;
+
+        loop {
+            match self.recv() {
+                Ok(_) => {}
+                Err(RLBotError::Connection(_)) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(self.received)
+    }
+
+    fn record(&mut self, packet: ClientPacket) {
+        if let ClientPacket::ConnectionSettings(x) = &packet {
+            self.connection_settings = Some(x.clone());
+        }
+
+        self.received.push(packet);
+    }
+
+    /// Every packet received from the bot so far, in order
+    #[must_use]
+    pub fn received(&self) -> &[ClientPacket] {
+        &self.received
+    }
+
+    pub fn player_inputs(&self) -> impl Iterator<Item = &PlayerInput> {
+        self.received.iter().filter_map(|packet| match packet {
+            ClientPacket::PlayerInput(x) => Some(x),
+            _ => None,
+        })
+    }
+
+    pub fn render_groups(&self) -> impl Iterator<Item = &RenderGroup> {
+        self.received.iter().filter_map(|packet| match packet {
+            ClientPacket::RenderGroup(x) => Some(x),
+            _ => None,
+        })
+    }
+
+    pub fn desired_game_states(&self) -> impl Iterator<Item = &DesiredGameState> {
+        self.received.iter().filter_map(|packet| match packet {
+            ClientPacket::DesiredGameState(x) => Some(x),
+            _ => None,
+        })
+    }
+}
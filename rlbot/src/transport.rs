@@ -0,0 +1,178 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// A byte stream that an [`RLBotConnection`](crate::RLBotConnection) can run over
+pub trait Transport: Read + Write {
+    /// Switches between blocking and non-blocking reads.
+    /// Non-blocking reads return [`io::ErrorKind::WouldBlock`] when no data is available.
+    /// Returns [`io::ErrorKind::Unsupported`] by default, in which case the connection only
+    /// returns packets that were already buffered instead of reading without blocking.
+    fn set_nonblocking(&self, _nonblocking: bool) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Creates another handle to the same stream,
+    /// used by [`RLBotConnection::split`](crate::RLBotConnection::split).
+    /// Returns [`io::ErrorKind::Unsupported`] by default.
+    fn try_clone(&self) -> io::Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+impl Transport for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        TcpStream::try_clone(self)
+    }
+}
+
+#[cfg(unix)]
+impl Transport for std::os::unix::net::UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        std::os::unix::net::UnixStream::set_nonblocking(self, nonblocking)
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        std::os::unix::net::UnixStream::try_clone(self)
+    }
+}
+
+#[derive(Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+    readable: Condvar,
+}
+
+#[derive(Default)]
+struct PipeState {
+    buf: VecDeque<u8>,
+    closed: bool,
+}
+
+impl Pipe {
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.readable.notify_all();
+    }
+}
+
+/// The pipes of one end, closed once every handle to that end has been dropped
+struct Pipes {
+    incoming: Arc<Pipe>,
+    outgoing: Arc<Pipe>,
+}
+
+impl Drop for Pipes {
+    fn drop(&mut self) {
+        self.incoming.close();
+        self.outgoing.close();
+    }
+}
+
+/// One end of an in-memory duplex stream, created with [`MemoryStream::pair`].
+/// Reading returns EOF once the other end has been dropped.
+pub struct MemoryStream {
+    pipes: Arc<Pipes>,
+    nonblocking: AtomicBool,
+}
+
+impl MemoryStream {
+    /// Creates two connected streams, everything written to one can be read from the other
+    #[must_use]
+    pub fn pair() -> (Self, Self) {
+        let a_to_b = Arc::new(Pipe::default());
+        let b_to_a = Arc::new(Pipe::default());
+
+        (
+            Self::new(Pipes {
+                incoming: b_to_a.clone(),
+                outgoing: a_to_b.clone(),
+            }),
+            Self::new(Pipes {
+                incoming: a_to_b,
+                outgoing: b_to_a,
+            }),
+        )
+    }
+
+    fn new(pipes: Pipes) -> Self {
+        Self {
+            pipes: Arc::new(pipes),
+            nonblocking: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut state = self.pipes.incoming.state.lock().unwrap();
+        while state.buf.is_empty() {
+            if state.closed {
+                return Ok(0);
+            }
+
+            if self.nonblocking.load(Ordering::Relaxed) {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+
+            state = self.pipes.incoming.readable.wait(state).unwrap();
+        }
+
+        let len = buf.len().min(state.buf.len());
+        for (dst, src) in buf.iter_mut().zip(state.buf.drain(..len)) {
+            *dst = src;
+        }
+
+        Ok(len)
+    }
+}
+
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.pipes.outgoing.state.lock().unwrap();
+        if state.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+
+        state.buf.extend(buf);
+        self.pipes.outgoing.readable.notify_all();
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MemoryStream {
+    /// Only affects this handle, unlike sockets
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            pipes: self.pipes.clone(),
+            nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Relaxed)),
+        })
+    }
+}
@@ -1,6 +1,12 @@
-use std::{env, io::Write, mem};
+use std::{env, mem, sync::Arc, vec};
 
-use crate::{Packet, RLBotConnection, RLBotError};
+use crate::{
+    ClientPacket, RLBotConnection, RLBotError, ServerPacket,
+    context::TickPacket,
+    flat::{BallPrediction, ControllableTeamInfo, GamePacket},
+    runner::{RunnerConfig, RunnerStats},
+    transport::Transport,
+};
 
 pub struct RLBotEnvironment {
     /// Will fallback to 127.0.0.1:23234
@@ -30,9 +36,172 @@ impl RLBotEnvironment {
     }
 }
 
+/// What runners do with packets other than the ones they handle every tick,
+/// e.g. a new [`MatchConfiguration`](crate::flat::MatchConfiguration) in the middle of a session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnexpectedPacketPolicy {
+    /// Pass them to `on_match_configuration`, `on_field_info` and `on_controllable_team_info`,
+    /// and ignore anything else
+    #[default]
+    Callbacks,
+    /// Stop the runner with [`RLBotError::UnexpectedPacket`]
+    Error,
+}
+
+impl UnexpectedPacketPolicy {
+    /// Returns the packet if it should be passed to the callbacks
+    pub(crate) fn check(self, packet: ServerPacket) -> Result<ServerPacket, RLBotError> {
+        match self {
+            Self::Callbacks => Ok(packet),
+            Self::Error => Err(RLBotError::UnexpectedPacket(Box::new(packet.into()))),
+        }
+    }
+}
+
+/// What runners do with [`PlayerInput`](crate::flat::PlayerInput)s for cars the bot doesn't control
+/// and with several inputs for the same car in one go
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputPolicy {
+    /// Send them anyway
+    #[default]
+    Forward,
+    /// Send them anyway, but count them in [`RunnerStats::input_violations`]
+    Warn,
+    /// Drop foreign inputs and all but the last input for each car
+    Coalesce,
+    /// Stop the runner with [`RLBotError::ForeignPlayerInput`] or [`RLBotError::DuplicatePlayerInput`]
+    Reject,
+}
+
+/// Applies an [`InputPolicy`], keeping its buffers between ticks
+#[derive(Debug, Clone)]
+pub(crate) struct InputChecker {
+    policy: InputPolicy,
+    runner_stats: Arc<RunnerStats>,
+    /// Cars with an input in the packets being checked
+    seen: Vec<u32>,
+    /// Packets to drop with [`InputPolicy::Coalesce`], in descending order
+    to_remove: Vec<usize>,
+}
+
+impl InputChecker {
+    pub fn new(config: &RunnerConfig) -> Self {
+        Self {
+            policy: config.input_policy,
+            runner_stats: config.runner_stats.clone(),
+            seen: Vec::new(),
+            to_remove: Vec::new(),
+        }
+    }
+
+    /// Applies the policy to packets queued by a bot controlling the cars with the `controlled` indices
+    pub fn apply(
+        &mut self,
+        packets: &mut Vec<ClientPacket>,
+        controlled: &[u32],
+    ) -> Result<(), RLBotError> {
+        if self.policy == InputPolicy::Forward {
+            return Ok(());
+        }
+
+        // the last input for a car wins, so go back to front
+        self.seen.clear();
+        self.to_remove.clear();
+        for (i, packet) in packets.iter().enumerate().rev() {
+            let ClientPacket::PlayerInput(input) = packet else {
+                continue;
+            };
+            let index = input.player_index;
+
+            let err = if !controlled.contains(&index) {
+                RLBotError::ForeignPlayerInput(index)
+            } else if self.seen.contains(&index) {
+                RLBotError::DuplicatePlayerInput(index)
+            } else {
+                self.seen.push(index);
+                continue;
+            };
+
+            match self.policy {
+                InputPolicy::Forward => unreachable!(),
+                InputPolicy::Warn => self.runner_stats.record_input_violation(),
+                InputPolicy::Coalesce => self.to_remove.push(i),
+                InputPolicy::Reject => return Err(err),
+            }
+        }
+
+        // indices are in descending order, so removing doesn't shift the next ones
+        for &i in &self.to_remove {
+            packets.remove(i);
+        }
+
+        Ok(())
+    }
+}
+
+/// Which [`GamePacket`](crate::flat::GamePacket)s runners tick with
+/// when several arrived since the last tick.
+///
+/// Either way, every tick passes the bot the [`MatchComm`](crate::flat::MatchComm)s
+/// received since the last tick first, then the latest ball prediction, then the game packet.
+/// Comms and ball predictions received without a game packet after them
+/// are passed on right away instead of being dropped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FramePolicy {
+    /// Only tick with the newest game packet, skipping ones the bot was too slow for
+    #[default]
+    LatestOnly,
+    /// Tick once for every game packet, in the order they arrived
+    EveryPacket,
+}
+
+/// The last game packet and ball prediction a runner handled, see [`TickPacket`]
+#[derive(Default)]
+pub(crate) struct LastPackets {
+    game_packet: Option<GamePacket>,
+    ball_prediction: Option<BallPrediction>,
+}
+
+impl LastPackets {
+    /// Keeps the ball prediction for the next ticks and returns it to pass on
+    pub fn ball_prediction(&mut self, ball_prediction: BallPrediction) -> &BallPrediction {
+        self.ball_prediction.insert(ball_prediction)
+    }
+
+    /// Calls `tick` with `game_packet`, which is kept as the previous game packet afterwards
+    pub fn tick<R>(
+        &mut self,
+        game_packet: GamePacket,
+        tick: impl FnOnce(TickPacket<'_>) -> R,
+    ) -> R {
+        let result = tick(TickPacket::new(
+            &game_packet,
+            self.game_packet.as_ref(),
+            self.ball_prediction.as_ref(),
+        ));
+        self.game_packet = Some(game_packet);
+
+        result
+    }
+
+    /// Forgets everything, e.g. because it belongs to the previous match
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// The player indices a bot controls, for [`InputChecker::apply`]
+pub(crate) fn controlled_indices(controllable_team_info: &ControllableTeamInfo) -> Vec<u32> {
+    controllable_team_info
+        .controllables
+        .iter()
+        .map(|controllable| controllable.index)
+        .collect()
+}
+
 /// A queue of packets to be sent to RLBotServer
 pub struct PacketQueue {
-    internal_queue: Vec<Packet>,
+    internal_queue: Vec<ClientPacket>,
 }
 
 impl Default for PacketQueue {
@@ -49,35 +218,42 @@ impl PacketQueue {
         }
     }
 
-    pub fn push(&mut self, packet: impl Into<Packet>) {
+    pub fn push(&mut self, packet: impl Into<ClientPacket>) {
         self.internal_queue.push(packet.into());
     }
 
-    pub(crate) fn empty(&mut self) -> Vec<Packet> {
+    pub(crate) fn len(&self) -> usize {
+        self.internal_queue.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.internal_queue.is_empty()
+    }
+
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.internal_queue.truncate(len);
+    }
+
+    pub(crate) fn empty(&mut self) -> Vec<ClientPacket> {
         mem::take(&mut self.internal_queue)
     }
+
+    /// Like [`Self::empty`], but applies an [`InputPolicy`] to the packets
+    /// and keeps the queue's allocation for the next tick
+    pub(crate) fn empty_checked(
+        &mut self,
+        input_checker: &mut InputChecker,
+        controlled: &[u32],
+    ) -> Result<vec::Drain<'_, ClientPacket>, RLBotError> {
+        input_checker.apply(&mut self.internal_queue, controlled)?;
+
+        Ok(self.internal_queue.drain(..))
+    }
 }
 
 pub(crate) fn write_multiple_packets(
-    connection: &mut RLBotConnection,
-    packets: impl Iterator<Item = Packet>,
+    connection: &mut RLBotConnection<impl Transport>,
+    packets: impl Iterator<Item = ClientPacket>,
 ) -> Result<(), RLBotError> {
-    let to_write = packets
-        // convert Packet to Vec<u8> that RLBotServer can understand
-        .flat_map(|x| {
-            let data_type_bin = x.data_type().to_be_bytes().to_vec();
-            let payload = x.build(&mut connection.builder);
-            let data_len_bin = u16::try_from(payload.len())
-                .expect("Payload can't be greater than a u16")
-                .to_be_bytes()
-                .to_vec();
-
-            [data_type_bin, data_len_bin, payload].concat()
-        })
-        .collect::<Vec<_>>();
-
-    connection.stream.write_all(&to_write)?;
-    connection.stream.flush()?;
-
-    Ok(())
+    connection.send_packets(packets)
 }
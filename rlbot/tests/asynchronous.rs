@@ -0,0 +1,265 @@
+use std::thread::{self, JoinHandle};
+
+use rlbot::{
+    ClientPacket, ServerPacket, StartingInfo,
+    agents::Agent,
+    asynchronous::AsyncRLBotConnection,
+    context::{AgentContext, HivemindContext, ScriptContext, TickPacket},
+    flat::{
+        ControllableInfo, ControllableTeamInfo, ControllerState, FieldInfo, GamePacket, MatchComm,
+        MatchConfiguration, PlayerInput,
+    },
+    hivemind::Hivemind,
+    runner::Runner,
+    scripts::Script,
+    testing::MockListener,
+    util::PacketQueue,
+};
+
+fn starting_info() -> StartingInfo {
+    StartingInfo {
+        controllable_team_info: ControllableTeamInfo {
+            team: 0,
+            controllables: vec![
+                ControllableInfo {
+                    index: 0,
+                    spawn_id: 10,
+                },
+                ControllableInfo {
+                    index: 1,
+                    spawn_id: 11,
+                },
+            ],
+        },
+        match_configuration: MatchConfiguration::default(),
+        field_info: FieldInfo {
+            boost_pads: Vec::new(),
+            goals: Vec::new(),
+        },
+    }
+}
+
+#[tokio::test]
+async fn connection_round_trip() {
+    let listener = MockListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let server = thread::spawn(move || {
+        let mut server = listener.accept().unwrap();
+        assert!(matches!(server.recv().unwrap(), ClientPacket::InitComplete));
+        server.send(GamePacket::default()).unwrap();
+        server
+    });
+
+    let mut connection = AsyncRLBotConnection::new(&addr).await.unwrap();
+    assert!(connection.try_recv_packet().unwrap().is_none());
+
+    connection
+        .send_packet(ClientPacket::InitComplete)
+        .await
+        .unwrap();
+    assert!(matches!(
+        connection.recv_packet().await.unwrap(),
+        ServerPacket::GamePacket(_)
+    ));
+
+    server.join().unwrap();
+}
+
+/// Serves one bot over TCP for two matches with one tick each,
+/// waiting for `per_tick` packets after every tick. Returns the address to connect to
+/// and a handle that returns a summary of everything the bot sent.
+fn serve(per_tick: usize) -> (String, JoinHandle<Vec<String>>) {
+    let listener = MockListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    let server = thread::spawn(move || {
+        let mut server = listener.accept().unwrap();
+
+        for _ in 0..2 {
+            server.handshake(starting_info()).unwrap();
+            server.send(GamePacket::default()).unwrap();
+
+            let mut received = 0;
+            server
+                .recv_until(|_| {
+                    received += 1;
+                    received == per_tick
+                })
+                .unwrap();
+        }
+
+        server.finish().unwrap().iter().map(summary).collect()
+    });
+
+    (addr, server)
+}
+
+fn summary(packet: &ClientPacket) -> String {
+    match packet {
+        ClientPacket::ConnectionSettings(_) => "settings".into(),
+        ClientPacket::InitComplete => "init".into(),
+        ClientPacket::PlayerInput(x) => {
+            format!("input {} {}", x.player_index, x.controller_state.throttle)
+        }
+        ClientPacket::MatchComm(x) => format!("comm {}", x.display.as_deref().unwrap_or("")),
+        packet => panic!("unexpected packet: {packet:?}"),
+    }
+}
+
+fn send_input(player_index: u32, matches: u32, packet_queue: &mut PacketQueue) {
+    packet_queue.push(PlayerInput {
+        player_index,
+        controller_state: ControllerState {
+            throttle: matches as f32,
+            ..Default::default()
+        },
+    });
+}
+
+/// Sends how many matches it started as throttle every tick
+struct MatchCountingAgent {
+    index: u32,
+    matches: u32,
+}
+
+impl Agent for MatchCountingAgent {
+    fn new(context: AgentContext, _packet_queue: &mut PacketQueue) -> Self {
+        Self {
+            index: context.controllable_info.index,
+            matches: 1,
+        }
+    }
+
+    fn tick(&mut self, _game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        send_input(self.index, self.matches, packet_queue);
+    }
+
+    fn on_match_start(&mut self, _context: AgentContext, _packet_queue: &mut PacketQueue) {
+        self.matches += 1;
+    }
+}
+
+#[tokio::test]
+async fn runs_agents() {
+    let (addr, server) = serve(2);
+
+    let connection = AsyncRLBotConnection::new(&addr).await.unwrap();
+    Runner::agents()
+        .agent_id("rlbot/async-test")
+        .close_between_matches(false)
+        .run_async::<MatchCountingAgent>(connection)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        server.join().unwrap(),
+        [
+            "settings",
+            "init",
+            "input 0 1",
+            "input 1 1",
+            "init",
+            "input 0 2",
+            "input 1 2"
+        ]
+    );
+}
+
+/// Like [`MatchCountingAgent`], for every car
+struct MatchCountingHivemind {
+    indices: Vec<u32>,
+    matches: u32,
+}
+
+impl Hivemind for MatchCountingHivemind {
+    fn new(context: HivemindContext, _packet_queue: &mut PacketQueue) -> Self {
+        Self {
+            indices: context
+                .controllable_team_info
+                .controllables
+                .iter()
+                .map(|controllable| controllable.index)
+                .collect(),
+            matches: 1,
+        }
+    }
+
+    fn tick(&mut self, _game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        for &index in &self.indices {
+            send_input(index, self.matches, packet_queue);
+        }
+    }
+
+    fn on_match_start(&mut self, _context: HivemindContext, _packet_queue: &mut PacketQueue) {
+        self.matches += 1;
+    }
+}
+
+#[tokio::test]
+async fn runs_hivemind() {
+    let (addr, server) = serve(2);
+
+    let connection = AsyncRLBotConnection::new(&addr).await.unwrap();
+    Runner::hivemind()
+        .agent_id("rlbot/async-test")
+        .close_between_matches(false)
+        .run_async::<MatchCountingHivemind>(connection)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        server.join().unwrap(),
+        [
+            "settings",
+            "init",
+            "input 0 1",
+            "input 1 1",
+            "init",
+            "input 0 2",
+            "input 1 2"
+        ]
+    );
+}
+
+/// Sends how many matches it started as a comm every tick
+struct MatchCountingScript {
+    matches: u32,
+}
+
+impl Script for MatchCountingScript {
+    fn new(_context: ScriptContext, _packet_queue: &mut PacketQueue) -> Self {
+        Self { matches: 1 }
+    }
+
+    fn tick(&mut self, _game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        packet_queue.push(MatchComm {
+            index: 0,
+            team: 0,
+            team_only: false,
+            display: Some(self.matches.to_string()),
+            content: Vec::new(),
+        });
+    }
+
+    fn on_match_start(&mut self, _context: ScriptContext, _packet_queue: &mut PacketQueue) {
+        self.matches += 1;
+    }
+}
+
+#[tokio::test]
+async fn runs_script() {
+    let (addr, server) = serve(1);
+
+    let connection = AsyncRLBotConnection::new(&addr).await.unwrap();
+    Runner::script()
+        .agent_id("rlbot/async-test")
+        .close_between_matches(false)
+        .run_async::<MatchCountingScript>(connection)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        server.join().unwrap(),
+        ["settings", "init", "comm 1", "init", "comm 2"]
+    );
+}
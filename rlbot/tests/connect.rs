@@ -0,0 +1,64 @@
+use std::{
+    net::TcpListener,
+    thread,
+    time::{Duration, Instant},
+};
+
+use rlbot::{RLBotConnection, RLBotError, connect::ConnectOptions};
+
+#[test]
+fn retries_until_server_listens() {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let server = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        let listener = TcpListener::bind(addr).unwrap();
+        listener.accept().unwrap()
+    });
+
+    let options = ConnectOptions {
+        initial_backoff: Duration::from_millis(20),
+        ..ConnectOptions::wait_for_server(Duration::from_secs(10))
+    };
+    RLBotConnection::connect(&addr.to_string(), &options).unwrap();
+
+    server.join().unwrap();
+}
+
+#[test]
+fn gives_up_at_deadline() {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let start = Instant::now();
+    let res = RLBotConnection::connect(
+        &addr.to_string(),
+        &ConnectOptions::wait_for_server(Duration::from_millis(300)),
+    );
+
+    assert!(matches!(res, Err(RLBotError::Timeout)));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn read_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    let mut connection = RLBotConnection::connect(
+        &addr,
+        &ConnectOptions {
+            read_timeout: Some(Duration::from_millis(50)),
+            ..ConnectOptions::default()
+        },
+    )
+    .unwrap();
+    let _server_side = listener.accept().unwrap();
+
+    assert!(matches!(connection.recv_packet(), Err(RLBotError::Timeout)));
+}
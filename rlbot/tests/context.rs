@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use rlbot::{
+    context::{AgentContext, HivemindContext, ScriptContext},
+    flat::{
+        ControllableInfo, ControllableTeamInfo, FieldInfo, MatchConfiguration, PlayerConfiguration,
+        ScriptConfiguration,
+    },
+};
+
+fn player(name: &str, team: u32, spawn_id: i32) -> PlayerConfiguration {
+    PlayerConfiguration {
+        name: name.into(),
+        team,
+        spawn_id,
+        ..Default::default()
+    }
+}
+
+fn match_configuration() -> MatchConfiguration {
+    MatchConfiguration {
+        player_configurations: vec![
+            player("Blue 1", 0, 10),
+            player("Blue 2", 0, 11),
+            player("Blue 3", 0, 12),
+            player("Orange", 1, 20),
+        ],
+        script_configurations: vec![ScriptConfiguration {
+            name: "Script".into(),
+            agent_id: "rlbot/context-test".into(),
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+fn names<'a>(players: impl Iterator<Item = &'a PlayerConfiguration>) -> Vec<&'a str> {
+    players.map(|player| player.name.as_str()).collect()
+}
+
+#[test]
+fn agent_context() {
+    let context = AgentContext {
+        team: 0,
+        controllable_info: ControllableInfo {
+            index: 1,
+            spawn_id: 11,
+        },
+        match_configuration: Arc::new(match_configuration()),
+        field_info: Arc::new(FieldInfo::default()),
+        stats: Arc::default(),
+    };
+
+    assert_eq!(context.own_name(), Some("Blue 2"));
+    assert_eq!(names(context.teammates()), ["Blue 1", "Blue 3"]);
+    assert_eq!(names(context.opponents()), ["Orange"]);
+}
+
+#[test]
+fn hivemind_context() {
+    let context = HivemindContext {
+        controllable_team_info: ControllableTeamInfo {
+            team: 0,
+            controllables: vec![
+                ControllableInfo {
+                    index: 2,
+                    spawn_id: 12,
+                },
+                ControllableInfo {
+                    index: 0,
+                    spawn_id: 10,
+                },
+            ],
+        },
+        match_configuration: match_configuration(),
+        field_info: FieldInfo::default(),
+    };
+
+    assert_eq!(
+        names(context.own_player_configurations()),
+        ["Blue 3", "Blue 1"]
+    );
+    assert_eq!(names(context.teammates()), ["Blue 2"]);
+    assert_eq!(names(context.opponents()), ["Orange"]);
+}
+
+#[test]
+fn script_context() {
+    let mut context = ScriptContext {
+        agent_id: "rlbot/context-test".into(),
+        match_configuration: match_configuration(),
+        field_info: FieldInfo::default(),
+    };
+
+    assert_eq!(context.own_name(), Some("Script"));
+
+    context.agent_id = "someone/else".into();
+    assert!(context.own_script_configuration().is_none());
+}
@@ -0,0 +1,173 @@
+use rlbot::{
+    ClientPacket, Packet, PacketParseError, PacketRef, ServerPacket,
+    flat::{
+        Color, ConnectionSettings, DesiredGameState, GamePacket, Line3D, MatchComm,
+        MatchConfiguration, RenderGroup, RenderMessage, RenderType, StopCommand, String2D,
+    },
+    planus,
+};
+
+/// Small deterministic xorshift so the corpus is the same on every run
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+/// Valid flatbuffers to mutate with their data types,
+/// so the corpus also reaches past root validation
+fn seeds() -> Vec<(u16, Vec<u8>)> {
+    let mut builder = planus::Builder::new();
+
+    [
+        Packet::ConnectionSettings(ConnectionSettings {
+            agent_id: "rlbot/fuzz".into(),
+            wants_ball_predictions: true,
+            wants_comms: true,
+            close_between_matches: false,
+        }),
+        Packet::StopCommand(StopCommand {
+            shutdown_server: true,
+        }),
+        Packet::MatchComm(MatchComm {
+            index: 1,
+            team: 0,
+            team_only: false,
+            display: Some("gg".into()),
+            content: vec![1, 2, 3, 4],
+        }),
+        Packet::GamePacket(GamePacket::default()),
+        Packet::MatchConfiguration(MatchConfiguration {
+            launcher_arg: "fuzz".into(),
+            game_map_upk: "Stadium_P".into(),
+            ..Default::default()
+        }),
+        Packet::RenderGroup(RenderGroup {
+            render_messages: vec![
+                RenderMessage {
+                    variety: RenderType::Line3D(Box::new(Line3D {
+                        start: Box::default(),
+                        end: Box::default(),
+                    })),
+                    color: Color::default(),
+                },
+                RenderMessage {
+                    variety: RenderType::String2D(Box::new(String2D {
+                        text: "fuzz".into(),
+                        ..Default::default()
+                    })),
+                    color: Color::default(),
+                },
+            ],
+            id: 3,
+        }),
+        Packet::DesiredGameState(DesiredGameState::default()),
+    ]
+    .into_iter()
+    .map(|packet| (packet.data_type(), packet.build(&mut builder)))
+    .collect()
+}
+
+/// Truncated and mutated copies of `seed`
+fn mutations(seed: &[u8], rng: &mut XorShift) -> Vec<Vec<u8>> {
+    let mut mutations = Vec::new();
+
+    for len in 0..seed.len() {
+        mutations.push(seed[..len].to_vec());
+    }
+
+    for i in 0..seed.len() {
+        for flip in [0x01, 0x80, 0xFF] {
+            let mut mutated = seed.to_vec();
+            mutated[i] ^= flip;
+            mutations.push(mutated);
+        }
+
+        let mut mutated = seed.to_vec();
+        mutated[i] = rng.next() as u8;
+        mutations.push(mutated);
+    }
+
+    mutations
+}
+
+fn corpus() -> Vec<Vec<u8>> {
+    let mut rng = XorShift(0x5EED_0123_4567);
+    let mut corpus = vec![Vec::new(), vec![0; 64], vec![0xFF; 64]];
+
+    for len in [1, 3, 4, 7, 8, 16, 32, 100, 1024] {
+        for _ in 0..32 {
+            corpus.push(rng.bytes(len));
+        }
+    }
+
+    for (_, seed) in seeds() {
+        corpus.extend(mutations(&seed, &mut rng));
+        corpus.push(seed);
+    }
+
+    corpus
+}
+
+#[test]
+fn from_payload_never_panics() {
+    for payload in corpus() {
+        // data types past the known ones must be rejected, not panic
+        for data_type in 0..=20 {
+            let _ = Packet::from_payload(data_type, &payload);
+            let _ = PacketRef::from_payload(data_type, &payload);
+        }
+    }
+}
+
+#[test]
+fn from_payload_roundtrips_seeds() {
+    for (data_type, seed) in seeds() {
+        let packet = Packet::from_payload(data_type, &seed).expect("seed should parse");
+        assert_eq!(packet.data_type(), data_type);
+    }
+}
+
+#[test]
+fn from_payload_checks_direction() {
+    let seeds = seeds();
+
+    // ConnectionSettings and StopCommand are only sent by bots
+    for (data_type, seed) in &seeds[..2] {
+        assert!(ClientPacket::from_payload(*data_type, seed).is_ok());
+        assert!(matches!(
+            ServerPacket::from_payload(*data_type, seed),
+            Err(PacketParseError::WrongDirection(x)) if x == *data_type
+        ));
+    }
+
+    // MatchComm goes both ways
+    let (data_type, seed) = &seeds[2];
+    assert!(ClientPacket::from_payload(*data_type, seed).is_ok());
+    assert!(ServerPacket::from_payload(*data_type, seed).is_ok());
+}
+
+#[test]
+fn mutated_seeds_fail_conversion() {
+    let mut rng = XorShift(0x5EED_89AB_CDEF);
+
+    // some mutations keep the root table valid, but break something nested in it
+    let conversion_failed = seeds().iter().any(|(data_type, seed)| {
+        mutations(seed, &mut rng).iter().any(|mutated| {
+            matches!(
+                Packet::from_payload(*data_type, mutated),
+                Err(PacketParseError::ConversionFailed { data_type: x, .. }) if x == *data_type
+            )
+        })
+    });
+    assert!(conversion_failed);
+}
@@ -0,0 +1,1130 @@
+use std::{
+    sync::Mutex,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use rlbot::{
+    ClientPacket, RLBotConnection, RLBotError, ServerPacket, StartingInfo,
+    agents::{Agent, AgentError, AgentPanicPolicy, AgentScheduling, TickDeadline, ZeroCopyAgent},
+    context::{AgentContext, HivemindContext, ScriptContext, TickPacket},
+    flat::{
+        BallPrediction, ConnectionSettings, ControllableInfo, ControllableTeamInfo,
+        ControllerState, FieldInfo, GamePacket, GamePacketRef, MatchComm, MatchConfiguration,
+        PlayerInput,
+    },
+    handler::{PacketHandler, run_handler},
+    hivemind::{Hivemind, HivemindError, ZeroCopyHivemind},
+    runner::Runner,
+    scripts::Script,
+    testing::MockServer,
+    transport::MemoryStream,
+    util::{FramePolicy, InputPolicy, PacketQueue, UnexpectedPacketPolicy},
+};
+
+/// Sends a neutral input for each car it controls as soon as it starts,
+/// then every tick with how many match configurations it got since as throttle
+struct IdleHivemind {
+    indices: Vec<u32>,
+    match_configurations: u32,
+}
+
+impl Hivemind for IdleHivemind {
+    fn new(context: HivemindContext, packet_queue: &mut PacketQueue) -> Self {
+        let hivemind = Self {
+            indices: context
+                .controllable_team_info
+                .controllables
+                .iter()
+                .map(|controllable| controllable.index)
+                .collect(),
+            match_configurations: 0,
+        };
+        hivemind.send_inputs(packet_queue);
+        hivemind
+    }
+
+    fn tick(&mut self, _game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        self.send_inputs(packet_queue);
+    }
+
+    fn on_match_configuration(
+        &mut self,
+        _match_configuration: &MatchConfiguration,
+        _packet_queue: &mut PacketQueue,
+    ) {
+        self.match_configurations += 1;
+    }
+}
+
+impl IdleHivemind {
+    fn send_inputs(&self, packet_queue: &mut PacketQueue) {
+        for &player_index in &self.indices {
+            packet_queue.push(PlayerInput {
+                player_index,
+                controller_state: ControllerState {
+                    throttle: self.match_configurations as f32,
+                    ..Default::default()
+                },
+            });
+        }
+    }
+}
+
+/// Sends a neutral input for its car every tick
+struct IdleAgent(u32);
+
+impl Agent for IdleAgent {
+    fn new(context: AgentContext, _packet_queue: &mut PacketQueue) -> Self {
+        Self(context.controllable_info.index)
+    }
+
+    fn tick(&mut self, _game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        packet_queue.push(PlayerInput {
+            player_index: self.0,
+            controller_state: ControllerState::default(),
+        });
+    }
+}
+
+/// Indices of the dropped [`MatchCountingAgent`]s
+static DROPPED_AGENTS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Reports how many matches it started as throttle and how many it ended as steer
+struct MatchCountingAgent {
+    index: u32,
+    started: u32,
+    ended: u32,
+}
+
+impl Agent for MatchCountingAgent {
+    fn new(context: AgentContext, _packet_queue: &mut PacketQueue) -> Self {
+        Self {
+            index: context.controllable_info.index,
+            started: 1,
+            ended: 0,
+        }
+    }
+
+    fn tick(&mut self, _game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        packet_queue.push(PlayerInput {
+            player_index: self.index,
+            controller_state: ControllerState {
+                throttle: self.started as f32,
+                steer: self.ended as f32,
+                ..Default::default()
+            },
+        });
+    }
+
+    fn on_match_start(&mut self, context: AgentContext, _packet_queue: &mut PacketQueue) {
+        self.index = context.controllable_info.index;
+        self.started += 1;
+    }
+
+    fn on_match_end(&mut self) {
+        self.ended += 1;
+    }
+}
+
+impl Drop for MatchCountingAgent {
+    fn drop(&mut self) {
+        DROPPED_AGENTS.lock().unwrap().push(self.index);
+    }
+}
+
+/// Like [`IdleAgent`], but also sends an input on startup and takes forever to tick with index 1.
+/// Inputs from ticks have full throttle, the one from startup doesn't.
+struct SlowAgent(u32);
+
+impl Agent for SlowAgent {
+    fn new(context: AgentContext, packet_queue: &mut PacketQueue) -> Self {
+        let agent = Self(context.controllable_info.index);
+        agent.send_input(0.0, packet_queue);
+        agent
+    }
+
+    fn tick(&mut self, _game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        if self.0 == 1 {
+            thread::sleep(Duration::from_millis(500));
+        }
+
+        self.send_input(1.0, packet_queue);
+    }
+}
+
+impl SlowAgent {
+    fn send_input(&self, throttle: f32, packet_queue: &mut PacketQueue) {
+        packet_queue.push(PlayerInput {
+            player_index: self.0,
+            controller_state: ControllerState {
+                throttle,
+                ..Default::default()
+            },
+        });
+    }
+}
+
+/// Like [`IdleAgent`], but takes a while to tick with index 1
+/// and sends the frame number it ticked with as throttle
+struct StalledAgent(u32);
+
+impl Agent for StalledAgent {
+    fn new(context: AgentContext, _packet_queue: &mut PacketQueue) -> Self {
+        Self(context.controllable_info.index)
+    }
+
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        if self.0 == 1 {
+            thread::sleep(Duration::from_millis(300));
+        }
+
+        packet_queue.push(PlayerInput {
+            player_index: self.0,
+            controller_state: ControllerState {
+                throttle: game_packet.match_info.frame_num as f32,
+                ..Default::default()
+            },
+        });
+    }
+}
+
+/// Like [`IdleAgent`], but panics every tick with index 1
+struct PanickyAgent(u32);
+
+impl Agent for PanickyAgent {
+    fn new(context: AgentContext, _packet_queue: &mut PacketQueue) -> Self {
+        Self(context.controllable_info.index)
+    }
+
+    fn tick(&mut self, _game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        assert_ne!(self.0, 1, "agent 1 always panics");
+
+        packet_queue.push(PlayerInput {
+            player_index: self.0,
+            controller_state: ControllerState::default(),
+        });
+    }
+}
+
+/// Like [`IdleAgent`], but also sends an input for its teammate every tick
+struct MeddlingAgent(u32);
+
+impl Agent for MeddlingAgent {
+    fn new(context: AgentContext, _packet_queue: &mut PacketQueue) -> Self {
+        Self(context.controllable_info.index)
+    }
+
+    fn tick(&mut self, _game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        for player_index in [self.0, 1 - self.0] {
+            packet_queue.push(PlayerInput {
+                player_index,
+                controller_state: ControllerState::default(),
+            });
+        }
+    }
+}
+
+/// Like [`IdleAgent`], but steers with the frame number of the previous game packet
+struct HistoryAgent(u32);
+
+impl Agent for HistoryAgent {
+    fn new(context: AgentContext, _packet_queue: &mut PacketQueue) -> Self {
+        Self(context.controllable_info.index)
+    }
+
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        let previous = game_packet
+            .previous_game_packet()
+            .map_or(-1.0, |previous| previous.match_info.frame_num as f32);
+
+        packet_queue.push(PlayerInput {
+            player_index: self.0,
+            controller_state: ControllerState {
+                steer: previous,
+                ..Default::default()
+            },
+        });
+    }
+}
+
+/// Sends a [`MatchComm`] naming every callback it got, once per tick.
+/// Ticks are named with the frame number of their game packet.
+#[derive(Default)]
+struct RecordingScript(Vec<String>);
+
+impl Script for RecordingScript {
+    fn new(_context: ScriptContext, _packet_queue: &mut PacketQueue) -> Self {
+        Self::default()
+    }
+
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        self.0
+            .push(format!("tick {}", game_packet.match_info.frame_num));
+
+        for event in self.0.drain(..) {
+            packet_queue.push(MatchComm {
+                display: Some(event),
+                ..Default::default()
+            });
+        }
+    }
+
+    fn on_match_comm(&mut self, _match_comm: &MatchComm, _packet_queue: &mut PacketQueue) {
+        self.0.push("comm".into());
+    }
+
+    fn on_ball_prediction(&mut self, _ball_prediction: &BallPrediction) {
+        self.0.push("ball_prediction".into());
+    }
+}
+
+/// Completes the handshake once it has the field info and answers every game packet with a comm
+#[derive(Default)]
+struct CountingHandler {
+    game_packets: u8,
+    disconnected: bool,
+}
+
+impl PacketHandler for CountingHandler {
+    fn on_game_packet(&mut self, _game_packet: &GamePacket, packet_queue: &mut PacketQueue) {
+        self.game_packets += 1;
+        packet_queue.push(MatchComm {
+            content: vec![self.game_packets],
+            ..Default::default()
+        });
+    }
+
+    fn on_field_info(&mut self, _field_info: &FieldInfo, packet_queue: &mut PacketQueue) {
+        packet_queue.push(ClientPacket::InitComplete);
+    }
+
+    fn on_disconnect(&mut self) {
+        self.disconnected = true;
+    }
+}
+
+/// Sends an input for its car every tick, with how many ticks the runner timed so far as throttle
+struct ZeroCopyIdleAgent(AgentContext);
+
+impl ZeroCopyAgent for ZeroCopyIdleAgent {
+    fn new(context: AgentContext, _packet_queue: &mut PacketQueue) -> Self {
+        Self(context)
+    }
+
+    fn tick(&mut self, _game_packet: GamePacketRef<'_>, packet_queue: &mut PacketQueue) {
+        packet_queue.push(PlayerInput {
+            player_index: self.0.controllable_info.index,
+            controller_state: ControllerState {
+                throttle: self.0.stats.ticks() as f32,
+                ..Default::default()
+            },
+        });
+    }
+}
+
+/// Sends an input for each car it controls every tick, with how many comms it got as throttle
+struct ZeroCopyIdleHivemind {
+    indices: Vec<u32>,
+    comms: u32,
+}
+
+impl ZeroCopyHivemind for ZeroCopyIdleHivemind {
+    fn new(context: HivemindContext, _packet_queue: &mut PacketQueue) -> Self {
+        Self {
+            indices: context
+                .controllable_team_info
+                .controllables
+                .iter()
+                .map(|controllable| controllable.index)
+                .collect(),
+            comms: 0,
+        }
+    }
+
+    fn tick(&mut self, _game_packet: GamePacketRef<'_>, packet_queue: &mut PacketQueue) {
+        for &player_index in &self.indices {
+            packet_queue.push(PlayerInput {
+                player_index,
+                controller_state: ControllerState {
+                    throttle: self.comms as f32,
+                    ..Default::default()
+                },
+            });
+        }
+    }
+
+    fn on_match_comm(&mut self, _match_comm: &MatchComm, _packet_queue: &mut PacketQueue) {
+        self.comms += 1;
+    }
+}
+
+fn starting_info() -> StartingInfo {
+    StartingInfo {
+        controllable_team_info: ControllableTeamInfo {
+            team: 0,
+            controllables: vec![
+                ControllableInfo {
+                    index: 0,
+                    spawn_id: 10,
+                },
+                ControllableInfo {
+                    index: 1,
+                    spawn_id: 11,
+                },
+            ],
+        },
+        match_configuration: MatchConfiguration::default(),
+        field_info: FieldInfo {
+            boost_pads: Vec::new(),
+            goals: Vec::new(),
+        },
+    }
+}
+
+/// A bot running on its own thread, connected to a [`MockServer`] that completed the handshake
+struct Session<T> {
+    server: MockServer<MemoryStream>,
+    bot: JoinHandle<T>,
+}
+
+impl<T: Send + 'static> Session<T> {
+    fn start(
+        starting_info: StartingInfo,
+        bot: impl FnOnce(RLBotConnection<MemoryStream>) -> T + Send + 'static,
+    ) -> Self {
+        let (mut server, connection) = MockServer::in_memory();
+        let bot = thread::spawn(move || bot(connection));
+
+        server.handshake(starting_info).unwrap();
+
+        Self { server, bot }
+    }
+
+    /// Ends the session, returns everything the server received and what the bot returned
+    fn finish(self) -> (Vec<ClientPacket>, T) {
+        let received = self.server.finish().unwrap();
+
+        (received, self.bot.join().unwrap())
+    }
+
+    /// Waits for the bot to stop on its own, e.g. because of an error
+    fn join(self) -> T {
+        self.bot.join().unwrap()
+    }
+}
+
+/// The `(player_index, throttle)` of every input in `packets`
+fn inputs<'a>(packets: impl IntoIterator<Item = &'a ClientPacket>) -> Vec<(u32, f32)> {
+    packets
+        .into_iter()
+        .filter_map(|packet| match packet {
+            ClientPacket::PlayerInput(x) => Some((x.player_index, x.controller_state.throttle)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn hivemind_handshake() {
+    let session = Session::start(starting_info(), |connection| {
+        Runner::hivemind()
+            .agent_id("rlbot/mock-test")
+            .wants_comms(true)
+            .unexpected_packets(UnexpectedPacketPolicy::Error)
+            .run_with_connection::<IdleHivemind>(connection)
+    });
+
+    let connection_settings = session.server.connection_settings().unwrap();
+    assert_eq!(connection_settings.agent_id, "rlbot/mock-test");
+    assert!(connection_settings.wants_comms);
+
+    // inputs pushed in Hivemind::new arrive before InitComplete
+    let indices: Vec<_> = session
+        .server
+        .player_inputs()
+        .map(|x| x.player_index)
+        .collect();
+    assert_eq!(indices, [0, 1]);
+
+    session.finish().1.unwrap();
+}
+
+#[test]
+fn unexpected_packet_policy() {
+    // passed to the callback, which the hivemind reports in its next inputs
+    let mut session = Session::start(starting_info(), |connection| {
+        Runner::hivemind()
+            .agent_id("rlbot/mock-test")
+            .unexpected_packets(UnexpectedPacketPolicy::Callbacks)
+            .run_with_connection::<IdleHivemind>(connection)
+    });
+
+    session.server.send(MatchConfiguration::default()).unwrap();
+    session.server.send(GamePacket::default()).unwrap();
+    let input = session
+        .server
+        .recv_until(|packet| matches!(packet, ClientPacket::PlayerInput(_)));
+    assert_eq!(inputs([&input.unwrap()]), [(0, 1.0)]);
+
+    session.finish().1.unwrap();
+
+    // stops the runner
+    let mut session = Session::start(starting_info(), |connection| {
+        Runner::hivemind()
+            .agent_id("rlbot/mock-test")
+            .unexpected_packets(UnexpectedPacketPolicy::Error)
+            .run_with_connection::<IdleHivemind>(connection)
+    });
+
+    session.server.send(MatchConfiguration::default()).unwrap();
+    assert!(matches!(
+        session.join(),
+        Err(HivemindError::PacketParseError(
+            RLBotError::UnexpectedPacket(_)
+        ))
+    ));
+}
+
+#[test]
+fn hivemind_survives_between_matches() {
+    let mut session = Session::start(starting_info(), |connection| {
+        Runner::hivemind()
+            .agent_id("rlbot/mock-test")
+            .close_between_matches(false)
+            .unexpected_packets(UnexpectedPacketPolicy::Error)
+            .run_with_connection::<IdleHivemind>(connection)
+    });
+
+    let connection_settings = session.server.connection_settings().unwrap();
+    assert!(!connection_settings.close_between_matches);
+
+    // a new match restarts the hivemind instead of erroring
+    session.server.handshake(starting_info()).unwrap();
+
+    let indices: Vec<_> = session
+        .server
+        .player_inputs()
+        .map(|x| x.player_index)
+        .collect();
+    assert_eq!(indices, [0, 1, 0, 1]);
+
+    session.finish().1.unwrap();
+}
+
+#[test]
+fn match_start_waits_for_all_starting_info() {
+    let mut session = Session::start(starting_info(), |connection| {
+        Runner::hivemind()
+            .agent_id("rlbot/mock-test")
+            .close_between_matches(false)
+            .unexpected_packets(UnexpectedPacketPolicy::Error)
+            .run_with_connection::<IdleHivemind>(connection)
+    });
+
+    // the field info alone starts a new match, the game packet before the rest of it is dropped
+    let StartingInfo {
+        controllable_team_info,
+        match_configuration,
+        field_info,
+    } = starting_info();
+    session
+        .server
+        .send_all([
+            field_info.into(),
+            GamePacket::default().into(),
+            controllable_team_info.into(),
+            match_configuration.into(),
+        ])
+        .unwrap();
+    session
+        .server
+        .recv_until(|packet| matches!(packet, ClientPacket::InitComplete))
+        .unwrap();
+
+    // the match configuration was part of the starting info, not passed on by itself
+    session.server.send(GamePacket::default()).unwrap();
+    let (received, res) = session.finish();
+    res.unwrap();
+
+    let inputs = inputs(&received);
+    assert_eq!(
+        inputs,
+        [(0, 0.0), (1, 0.0), (0, 0.0), (1, 0.0), (0, 0.0), (1, 0.0)]
+    );
+}
+
+#[test]
+fn agents_survive_between_matches() {
+    /// Ticks once and returns the `(player_index, throttle, steer)` of the `n` inputs it caused
+    fn tick(server: &mut MockServer<MemoryStream>, n: usize) -> Vec<(u32, f32, f32)> {
+        let before = server.player_inputs().count();
+        server.send(GamePacket::default()).unwrap();
+
+        let mut inputs = 0;
+        server
+            .recv_until(|packet| {
+                inputs += usize::from(matches!(packet, ClientPacket::PlayerInput(_)));
+                inputs == n
+            })
+            .unwrap();
+
+        let mut ticked: Vec<_> = server
+            .player_inputs()
+            .skip(before)
+            .map(|x| {
+                let state = &x.controller_state;
+                (x.player_index, state.throttle, state.steer)
+            })
+            .collect();
+        ticked.sort_by_key(|x| x.0);
+        ticked
+    }
+
+    fn three_cars() -> StartingInfo {
+        let mut starting_info = starting_info();
+        starting_info
+            .controllable_team_info
+            .controllables
+            .push(ControllableInfo {
+                index: 2,
+                spawn_id: 12,
+            });
+        starting_info
+    }
+
+    let mut session = Session::start(three_cars(), |connection| {
+        Runner::agents()
+            .agent_id("rlbot/mock-test")
+            .scheduling(AgentScheduling::ThreadPerAgent)
+            .close_between_matches(false)
+            .run_with_connection::<MatchCountingAgent>(connection)
+    });
+    let tick_1 = tick(&mut session.server, 3);
+    assert_eq!(tick_1, [(0, 1.0, 0.0), (1, 1.0, 0.0), (2, 1.0, 0.0)]);
+
+    // the worker of the car that's gone ends its match and stops before the next match starts
+    session.server.handshake(starting_info()).unwrap();
+    assert_eq!(*DROPPED_AGENTS.lock().unwrap(), [2]);
+    let tick_2 = tick(&mut session.server, 2);
+    assert_eq!(tick_2, [(0, 2.0, 1.0), (1, 2.0, 1.0)]);
+
+    // a new worker is spawned for the car that's back
+    session.server.handshake(three_cars()).unwrap();
+    let tick_3 = tick(&mut session.server, 3);
+    assert_eq!(tick_3, [(0, 3.0, 2.0), (1, 3.0, 2.0), (2, 1.0, 0.0)]);
+
+    session.finish().1.unwrap();
+    let mut dropped = DROPPED_AGENTS.lock().unwrap().clone();
+    dropped.sort_unstable();
+    assert_eq!(dropped, [0, 1, 2, 2]);
+}
+
+#[test]
+fn agent_scheduling() {
+    for scheduling in [
+        AgentScheduling::ThreadPerAgent,
+        AgentScheduling::WorkerPool(2),
+        AgentScheduling::CallingThread,
+    ] {
+        let mut starting_info = starting_info();
+        starting_info
+            .controllable_team_info
+            .controllables
+            .push(ControllableInfo {
+                index: 2,
+                spawn_id: 12,
+            });
+
+        let mut session = Session::start(starting_info, move |connection| {
+            Runner::agents()
+                .agent_id("rlbot/mock-test")
+                .scheduling(scheduling)
+                .run_with_connection::<IdleAgent>(connection)
+        });
+
+        session.server.send(GamePacket::default()).unwrap();
+        let mut inputs = 0;
+        session
+            .server
+            .recv_until(|packet| {
+                inputs += usize::from(matches!(packet, ClientPacket::PlayerInput(_)));
+                inputs == 3
+            })
+            .unwrap();
+
+        let mut indices: Vec<_> = session
+            .server
+            .player_inputs()
+            .map(|x| x.player_index)
+            .collect();
+        indices.sort_unstable();
+        assert_eq!(indices, [0, 1, 2], "{scheduling:?}");
+
+        session.finish().1.unwrap();
+    }
+}
+
+#[test]
+fn tick_deadline() {
+    let mut session = Session::start(starting_info(), |connection| {
+        Runner::agents()
+            .agent_id("rlbot/mock-test")
+            .tick_deadline(TickDeadline {
+                timeout: Duration::from_millis(20),
+                repeat_last_input: true,
+            })
+            .run_with_connection::<SlowAgent>(connection)
+    });
+
+    session.server.send(GamePacket::default()).unwrap();
+
+    // agent 0 ticked and agent 1 missed the deadline, so its input from startup is repeated
+    let mut count = 0;
+    session
+        .server
+        .recv_until(|packet| {
+            count += usize::from(matches!(packet, ClientPacket::PlayerInput(_)));
+            count == 2
+        })
+        .unwrap();
+
+    assert_eq!(inputs(session.server.received())[2..], [(0, 1.0), (1, 0.0)]);
+
+    session.finish().1.unwrap();
+}
+
+#[test]
+fn late_inputs_are_not_repeated() {
+    let mut session = Session::start(starting_info(), |connection| {
+        Runner::agents()
+            .agent_id("rlbot/mock-test")
+            .tick_deadline(TickDeadline {
+                timeout: Duration::from_millis(20),
+                repeat_last_input: true,
+            })
+            .run_with_connection::<SlowAgent>(connection)
+    });
+
+    // tick until agent 1's late input from its first tick has been sent
+    while !session
+        .server
+        .player_inputs()
+        .any(|x| x.player_index == 1 && x.controller_state.throttle == 1.0)
+    {
+        session.server.send(GamePacket::default()).unwrap();
+        session
+            .server
+            .recv_until(
+                |packet| matches!(packet, ClientPacket::PlayerInput(x) if x.player_index == 0),
+            )
+            .unwrap();
+    }
+
+    let (received, res) = session.finish();
+    res.unwrap();
+
+    // every batch starts with agent 0's input, then either agent 1's late or repeated input
+    let inputs = inputs(&received);
+    let batches: Vec<_> = inputs
+        .chunk_by(|_, (player_index, _)| *player_index != 0)
+        .map(|batch| {
+            batch
+                .iter()
+                .map(|(player_index, _)| *player_index)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    assert!(batches.len() > 2);
+    assert!(batches.iter().all(|batch| batch == &[0, 1]), "{batches:?}");
+}
+
+#[test]
+fn stalled_agent_gets_latest_frame() {
+    let mut session = Session::start(starting_info(), |connection| {
+        Runner::agents()
+            .agent_id("rlbot/mock-test")
+            .tick_deadline(TickDeadline {
+                timeout: Duration::from_millis(20),
+                repeat_last_input: false,
+            })
+            .run_with_connection::<StalledAgent>(connection)
+    });
+
+    fn tick(server: &mut MockServer<MemoryStream>, frame_num: u32) {
+        let mut game_packet = GamePacket::default();
+        game_packet.match_info.frame_num = frame_num;
+        server.send(game_packet).unwrap();
+
+        server
+            .recv_until(
+                |packet| matches!(packet, ClientPacket::PlayerInput(x) if x.player_index == 0),
+            )
+            .unwrap();
+    }
+
+    /// The frames agent 1 ticked with, without repeats
+    fn stalled_frames(server: &MockServer<MemoryStream>) -> Vec<f32> {
+        let mut frames: Vec<_> = inputs(server.received())
+            .into_iter()
+            .filter_map(|(player_index, frame)| (player_index == 1).then_some(frame))
+            .collect();
+        frames.dedup();
+        frames
+    }
+
+    for frame_num in 1..=5 {
+        tick(&mut session.server, frame_num);
+    }
+
+    // agent 1's inputs are sent along with later ticks, so keep ticking until it caught up
+    while stalled_frames(&session.server).last() != Some(&5.0) {
+        tick(&mut session.server, 5);
+    }
+
+    // agent 1 was still busy with frame 1, then skipped straight to frame 5
+    assert_eq!(stalled_frames(&session.server), [1.0, 5.0]);
+
+    session.finish().1.unwrap();
+}
+
+#[test]
+fn stalled_agent_drops_old_comms() {
+    let runner = Runner::agents()
+        .agent_id("rlbot/mock-test")
+        .wants_comms(true)
+        .tick_deadline(TickDeadline {
+            timeout: Duration::from_millis(20),
+            repeat_last_input: false,
+        });
+    let stats = runner.stats();
+    let mut session = Session::start(starting_info(), move |connection| {
+        runner.run_with_connection::<SlowAgent>(connection)
+    });
+
+    // agent 1 is busy with this game packet while the comms pile up in its inbox
+    session.server.send(GamePacket::default()).unwrap();
+    session
+        .server
+        .recv_until(|packet| matches!(packet, ClientPacket::PlayerInput(x) if x.player_index == 0))
+        .unwrap();
+    for _ in 0..300 {
+        session.server.send(MatchComm::default()).unwrap();
+    }
+    session.server.send(GamePacket::default()).unwrap();
+    session
+        .server
+        .recv_until(|packet| matches!(packet, ClientPacket::PlayerInput(x) if x.player_index == 0))
+        .unwrap();
+
+    assert!(
+        stats.dropped_comms() >= 300 - 256,
+        "{}",
+        stats.dropped_comms()
+    );
+
+    session.finish().1.unwrap();
+}
+
+#[test]
+fn agent_panic_policy() {
+    for (policy, expected_panics) in [
+        (AgentPanicPolicy::Isolate, 1),
+        (AgentPanicPolicy::Restart, 2),
+    ] {
+        let runner = Runner::agents()
+            .agent_id("rlbot/mock-test")
+            .on_agent_panic(policy);
+        let stats = runner.stats();
+        let mut session = Session::start(starting_info(), move |connection| {
+            runner.run_with_connection::<PanickyAgent>(connection)
+        });
+
+        // agent 0 keeps playing, agent 1 only panics again if it was restarted
+        for _ in 0..2 {
+            session.server.send(GamePacket::default()).unwrap();
+            session
+                .server
+                .recv_until(|packet| matches!(packet, ClientPacket::PlayerInput(_)))
+                .unwrap();
+        }
+        assert_eq!(stats.agent_panics(), expected_panics, "{policy:?}");
+
+        session.finish().1.unwrap();
+    }
+
+    // stops the runner, also when the agents run on the calling thread
+    for scheduling in [
+        AgentScheduling::ThreadPerAgent,
+        AgentScheduling::CallingThread,
+    ] {
+        let mut session = Session::start(starting_info(), move |connection| {
+            Runner::agents()
+                .agent_id("rlbot/mock-test")
+                .scheduling(scheduling)
+                .run_with_connection::<PanickyAgent>(connection)
+        });
+
+        session.server.send(GamePacket::default()).unwrap();
+
+        let res = session.join();
+        assert!(matches!(res, Err(AgentError::AgentPanic)), "{scheduling:?}");
+    }
+}
+
+#[test]
+fn input_policy() {
+    for policy in [
+        InputPolicy::Forward,
+        InputPolicy::Warn,
+        InputPolicy::Coalesce,
+        InputPolicy::Reject,
+    ] {
+        let runner = Runner::agents()
+            .agent_id("rlbot/mock-test")
+            .input_policy(policy);
+        let stats = runner.stats();
+        let mut session = Session::start(starting_info(), move |connection| {
+            runner.run_with_connection::<MeddlingAgent>(connection)
+        });
+
+        session.server.send(GamePacket::default()).unwrap();
+
+        if policy == InputPolicy::Reject {
+            assert!(matches!(
+                session.join(),
+                Err(AgentError::PacketParseError(
+                    RLBotError::ForeignPlayerInput(_)
+                ))
+            ));
+            continue;
+        }
+
+        session
+            .server
+            .recv_until(|packet| matches!(packet, ClientPacket::PlayerInput(_)))
+            .unwrap();
+        let (received, res) = session.finish();
+        res.unwrap();
+
+        // each agent sent one input for the other agent's car
+        let (expected, violations) = match policy {
+            InputPolicy::Forward => (4, 0),
+            InputPolicy::Warn => (4, 2),
+            _ => (2, 0),
+        };
+        assert_eq!(inputs(&received).len(), expected, "{policy:?}");
+        assert_eq!(stats.input_violations(), violations, "{policy:?}");
+    }
+}
+
+#[test]
+fn frame_policy() {
+    for policy in [FramePolicy::EveryPacket, FramePolicy::LatestOnly] {
+        let mut session = Session::start(starting_info(), move |connection| {
+            Runner::script()
+                .agent_id("rlbot/mock-test")
+                .frame_policy(policy)
+                .run_with_connection::<RecordingScript>(connection)
+        });
+
+        /// Receives events until `others` of them weren't ticks and a tick came after them
+        fn recv_events(server: &mut MockServer<MemoryStream>, others: usize) -> Vec<String> {
+            let mut events: Vec<String> = Vec::new();
+            while events
+                .iter()
+                .filter(|event| !event.starts_with("tick"))
+                .count()
+                < others
+                || events.last().is_none_or(|event| !event.starts_with("tick"))
+            {
+                if let ClientPacket::MatchComm(x) = server.recv().unwrap() {
+                    events.push(x.display.unwrap());
+                }
+            }
+            events
+        }
+
+        fn game_packet(frame_num: u32) -> ServerPacket {
+            let mut game_packet = GamePacket::default();
+            game_packet.match_info.frame_num = frame_num;
+            game_packet.into()
+        }
+
+        // waits for the tick, so the next packets are drained separately
+        session
+            .server
+            .send_all([BallPrediction::default().into(), game_packet(1)])
+            .unwrap();
+        let mut events = recv_events(&mut session.server, 1);
+
+        // sent at once, so they're drained together
+        session
+            .server
+            .send_all([
+                MatchComm::default().into(),
+                game_packet(2),
+                BallPrediction::default().into(),
+                game_packet(3),
+                MatchComm::default().into(),
+                game_packet(4),
+            ])
+            .unwrap();
+        events.extend(recv_events(&mut session.server, 3));
+
+        session.finish().1.unwrap();
+
+        match policy {
+            FramePolicy::EveryPacket => assert_eq!(
+                events,
+                [
+                    "ball_prediction",
+                    "tick 1",
+                    "comm",
+                    "tick 2",
+                    "ball_prediction",
+                    "tick 3",
+                    "comm",
+                    "tick 4"
+                ]
+            ),
+            // the stale game packets are skipped, but everything received
+            // before the latest one is passed on before its tick
+            FramePolicy::LatestOnly => assert_eq!(
+                events,
+                [
+                    "ball_prediction",
+                    "tick 1",
+                    "comm",
+                    "comm",
+                    "ball_prediction",
+                    "tick 4"
+                ]
+            ),
+        }
+    }
+}
+
+#[test]
+fn previous_game_packet() {
+    for scheduling in [
+        AgentScheduling::ThreadPerAgent,
+        AgentScheduling::CallingThread,
+    ] {
+        let mut session = Session::start(starting_info(), move |connection| {
+            Runner::agents()
+                .agent_id("rlbot/mock-test")
+                .scheduling(scheduling)
+                .run_with_connection::<HistoryAgent>(connection)
+        });
+
+        let mut steers = Vec::new();
+        for frame_num in 1..=3 {
+            let mut game_packet = GamePacket::default();
+            game_packet.match_info.frame_num = frame_num;
+            session.server.send(game_packet).unwrap();
+
+            let packet = session
+                .server
+                .recv_until(
+                    |packet| matches!(packet, ClientPacket::PlayerInput(x) if x.player_index == 0),
+                )
+                .unwrap();
+            if let ClientPacket::PlayerInput(x) = packet {
+                steers.push(x.controller_state.steer);
+            }
+        }
+
+        session.finish().1.unwrap();
+
+        assert_eq!(steers, [-1.0, 1.0, 2.0], "{scheduling:?}");
+    }
+}
+
+#[test]
+fn packet_handler() {
+    let mut session = Session::start(starting_info(), |mut connection| {
+        connection.send_packet(ConnectionSettings::default())?;
+
+        let mut handler = CountingHandler::default();
+        run_handler(&mut handler, &mut connection).map(|()| handler)
+    });
+
+    for _ in 0..3 {
+        session.server.send(GamePacket::default()).unwrap();
+    }
+
+    let mut contents = Vec::new();
+    while contents.len() < 3 {
+        if let ClientPacket::MatchComm(x) = session.server.recv().unwrap() {
+            contents.push(x.content[0]);
+        }
+    }
+
+    let handler = session.finish().1.unwrap();
+
+    assert_eq!(contents, [1, 2, 3]);
+    assert_eq!(handler.game_packets, 3);
+    assert!(handler.disconnected);
+}
+
+#[test]
+fn packet_handler_disconnects_on_error() {
+    let mut session = Session::start(starting_info(), |mut connection| {
+        connection
+            .send_packet(ConnectionSettings::default())
+            .unwrap();
+
+        let mut handler = CountingHandler::default();
+        let result = run_handler(&mut handler, &mut connection);
+        (handler, result)
+    });
+
+    session.server.send(GamePacket::default()).unwrap();
+    // the connection closes without the server ending the session
+    let Session { server, bot } = session;
+    drop(server);
+
+    let (handler, result) = bot.join().unwrap();
+
+    assert!(result.is_err());
+    assert!(handler.disconnected);
+}
+
+#[test]
+fn zero_copy_agents() {
+    let mut session = Session::start(starting_info(), |connection| {
+        Runner::agents()
+            .agent_id("rlbot/mock-test")
+            .frame_policy(FramePolicy::EveryPacket)
+            .input_policy(InputPolicy::Reject)
+            .run_zero_copy::<ZeroCopyIdleAgent>(connection)
+    });
+
+    session.server.send(GamePacket::default()).unwrap();
+    session.server.send(GamePacket::default()).unwrap();
+
+    let (received, res) = session.finish();
+    res.unwrap();
+
+    assert_eq!(inputs(&received), [(0, 0.0), (1, 0.0), (0, 1.0), (1, 1.0)]);
+}
+
+#[test]
+fn zero_copy_hivemind() {
+    let mut session = Session::start(starting_info(), |connection| {
+        Runner::hivemind()
+            .agent_id("rlbot/mock-test")
+            .frame_policy(FramePolicy::EveryPacket)
+            .run_zero_copy::<ZeroCopyIdleHivemind>(connection)
+    });
+
+    session.server.send(MatchComm::default()).unwrap();
+    session.server.send(GamePacket::default()).unwrap();
+    session.server.send(GamePacket::default()).unwrap();
+
+    let (received, res) = session.finish();
+    res.unwrap();
+
+    assert_eq!(inputs(&received), [(0, 1.0), (1, 1.0), (0, 1.0), (1, 1.0)]);
+}
@@ -0,0 +1,395 @@
+use rlbot::{
+    ClientPacket, Packet, RLBotConnection, RLBotError, ServerPacket,
+    agents::Agent,
+    context::{AgentContext, HivemindContext, ScriptContext, TickPacket},
+    flat::{
+        BallPrediction, ConnectionSettings, ControllableInfo, ControllableTeamInfo,
+        ControllerState, FieldInfo, GamePacket, MatchComm, MatchConfiguration, PlayerInput,
+        StopCommand,
+    },
+    hivemind::Hivemind,
+    recording::{Direction, Recorder, SessionReader},
+    replay::{replay_agents, replay_hivemind, replay_script},
+    scripts::Script,
+    testing::MockServer,
+    transport::MemoryStream,
+    util::PacketQueue,
+};
+
+#[test]
+fn recorded_session_reads_back() {
+    let (bot_side, server_side) = MemoryStream::pair();
+
+    let mut bot = RLBotConnection::from_transport(Recorder::new(bot_side, Vec::new()).unwrap());
+    let mut server = MockServer::from_transport(server_side);
+
+    bot.send_packet(StopCommand {
+        shutdown_server: false,
+    })
+    .unwrap();
+    assert!(matches!(
+        server.recv().unwrap(),
+        ClientPacket::StopCommand(_)
+    ));
+
+    server
+        .send(MatchComm {
+            index: 0,
+            team: 1,
+            team_only: true,
+            display: Some("hi".into()),
+            content: vec![7; 3],
+        })
+        .unwrap();
+    server.send(ServerPacket::None).unwrap();
+    assert!(matches!(
+        bot.recv_packet().unwrap(),
+        ServerPacket::MatchComm(_)
+    ));
+    assert!(matches!(bot.recv_packet().unwrap(), ServerPacket::None));
+
+    let (_, session) = bot.into_transport().into_inner();
+    let recorded = SessionReader::new(session.as_slice())
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    let summary: Vec<_> = recorded
+        .iter()
+        .map(|x| (x.direction, x.packet.data_type()))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (Direction::Sent, 12),
+            (Direction::Received, 9),
+            (Direction::Received, 0)
+        ]
+    );
+
+    let Packet::MatchComm(match_comm) = &recorded[1].packet else {
+        unreachable!()
+    };
+    assert_eq!(match_comm.content, [7; 3]);
+    assert!(
+        recorded
+            .windows(2)
+            .all(|x| x[0].timestamp <= x[1].timestamp)
+    );
+}
+
+#[test]
+fn rejects_other_files() {
+    assert!(SessionReader::new(&b"definitely not a session"[..]).is_err());
+}
+
+/// Echoes its agent id back as a match comm on startup
+struct EchoScript;
+
+impl Script for EchoScript {
+    fn new(context: ScriptContext, packet_queue: &mut PacketQueue) -> Self {
+        packet_queue.push(MatchComm {
+            index: 0,
+            team: 0,
+            team_only: false,
+            display: None,
+            content: context.agent_id.into_bytes(),
+        });
+
+        Self
+    }
+
+    fn tick(&mut self, _game_packet: TickPacket<'_>, _packet_queue: &mut PacketQueue) {}
+}
+
+/// Records a session where the bot connects as `agent_id` if there is one, then gets
+/// the starting info for cars 0 and 1 followed by `packets`
+fn record_session(agent_id: Option<&str>, packets: Vec<ServerPacket>) -> Vec<u8> {
+    let (bot_side, server_side) = MemoryStream::pair();
+
+    let mut bot = RLBotConnection::from_transport(Recorder::new(bot_side, Vec::new()).unwrap());
+    let mut server = MockServer::from_transport(server_side);
+
+    if let Some(agent_id) = agent_id {
+        bot.send_packet(ConnectionSettings {
+            agent_id: agent_id.into(),
+            wants_ball_predictions: true,
+            wants_comms: true,
+            close_between_matches: true,
+        })
+        .unwrap();
+        server.recv().unwrap();
+    }
+
+    server
+        .send(ControllableTeamInfo {
+            team: 0,
+            controllables: vec![
+                ControllableInfo {
+                    index: 0,
+                    spawn_id: 10,
+                },
+                ControllableInfo {
+                    index: 1,
+                    spawn_id: 11,
+                },
+            ],
+        })
+        .unwrap();
+    server.send(MatchConfiguration::default()).unwrap();
+    server
+        .send(FieldInfo {
+            boost_pads: Vec::new(),
+            goals: Vec::new(),
+        })
+        .unwrap();
+    for packet in packets {
+        server.send(packet).unwrap();
+    }
+    server.send(ServerPacket::None).unwrap();
+    while !matches!(bot.recv_packet().unwrap(), ServerPacket::None) {}
+
+    bot.into_transport().into_inner().1
+}
+
+#[test]
+fn replays_recorded_script() {
+    let session = record_session(Some("rlbot/replay-test"), Vec::new());
+    let output =
+        replay_script::<EchoScript>(SessionReader::new(session.as_slice()).unwrap()).unwrap();
+
+    let [ClientPacket::MatchComm(match_comm)] = output.init.as_slice() else {
+        panic!("unexpected init output: {:?}", output.init);
+    };
+    assert_eq!(match_comm.content, b"rlbot/replay-test");
+    assert!(output.ticks.is_empty());
+}
+
+#[test]
+fn replay_script_needs_agent_id() {
+    let session = record_session(None, Vec::new());
+    let res = replay_script::<EchoScript>(SessionReader::new(session.as_slice()).unwrap());
+
+    assert!(matches!(res, Err(RLBotError::MissingAgentId)));
+}
+
+/// The `(player_index, throttle, steer, jump)` of every input in `packets`
+fn inputs(packets: &[ClientPacket]) -> Vec<(u32, f32, f32, bool)> {
+    packets
+        .iter()
+        .map(|packet| match packet {
+            ClientPacket::PlayerInput(input) => (
+                input.player_index,
+                input.controller_state.throttle,
+                input.controller_state.steer,
+                input.controller_state.jump,
+            ),
+            packet => panic!("unexpected packet: {packet:?}"),
+        })
+        .collect()
+}
+
+fn match_comm() -> ServerPacket {
+    MatchComm {
+        index: 0,
+        team: 0,
+        team_only: false,
+        display: None,
+        content: Vec::new(),
+    }
+    .into()
+}
+
+/// Reports how many ball predictions and comms it got as throttle and steer,
+/// and whether it got a previous game packet as jump
+struct CountingAgent {
+    index: u32,
+    ball_predictions: u32,
+    comms: u32,
+}
+
+impl Agent for CountingAgent {
+    fn new(context: AgentContext, _packet_queue: &mut PacketQueue) -> Self {
+        Self {
+            index: context.controllable_info.index,
+            ball_predictions: 0,
+            comms: 0,
+        }
+    }
+
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        packet_queue.push(PlayerInput {
+            player_index: self.index,
+            controller_state: ControllerState {
+                throttle: self.ball_predictions as f32,
+                steer: self.comms as f32,
+                jump: game_packet.previous_game_packet().is_some(),
+                ..Default::default()
+            },
+        });
+    }
+
+    fn on_match_comm(&mut self, _match_comm: &MatchComm, _packet_queue: &mut PacketQueue) {
+        self.comms += 1;
+    }
+
+    fn on_ball_prediction(&mut self, _ball_prediction: &BallPrediction) {
+        self.ball_predictions += 1;
+    }
+}
+
+/// Answers comms and ticks with a comm that has its index and what it answers as display
+struct AnsweringAgent(u32);
+
+impl AnsweringAgent {
+    fn answer(&self, to: &str, packet_queue: &mut PacketQueue) {
+        packet_queue.push(MatchComm {
+            index: self.0,
+            team: 0,
+            team_only: false,
+            display: Some(format!("{} {to}", self.0)),
+            content: Vec::new(),
+        });
+    }
+}
+
+impl Agent for AnsweringAgent {
+    fn new(context: AgentContext, packet_queue: &mut PacketQueue) -> Self {
+        let agent = Self(context.controllable_info.index);
+        agent.answer("new", packet_queue);
+        agent
+    }
+
+    fn tick(&mut self, _game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        self.answer("tick", packet_queue);
+    }
+
+    fn on_match_comm(&mut self, _match_comm: &MatchComm, packet_queue: &mut PacketQueue) {
+        self.answer("comm", packet_queue);
+    }
+}
+
+#[test]
+fn replay_keeps_agent_outputs_together() {
+    let session = record_session(
+        Some("rlbot/replay-test"),
+        vec![match_comm(), GamePacket::default().into()],
+    );
+    let output =
+        replay_agents::<AnsweringAgent>(SessionReader::new(session.as_slice()).unwrap()).unwrap();
+
+    let displays = |packets: &[ClientPacket]| -> Vec<String> {
+        packets
+            .iter()
+            .map(|packet| match packet {
+                ClientPacket::MatchComm(x) => x.display.clone().unwrap(),
+                packet => panic!("unexpected packet: {packet:?}"),
+            })
+            .collect()
+    };
+    assert_eq!(displays(&output.init), ["0 new", "1 new"]);
+    assert_eq!(
+        displays(&output.ticks[0]),
+        ["0 comm", "0 tick", "1 comm", "1 tick"]
+    );
+}
+
+#[test]
+fn replays_recorded_agents() {
+    let session = record_session(
+        Some("rlbot/replay-test"),
+        vec![
+            BallPrediction::default().into(),
+            match_comm(),
+            GamePacket::default().into(),
+            GamePacket::default().into(),
+            BallPrediction::default().into(),
+            GamePacket::default().into(),
+        ],
+    );
+    let output =
+        replay_agents::<CountingAgent>(SessionReader::new(session.as_slice()).unwrap()).unwrap();
+
+    assert!(output.init.is_empty());
+    let ticks: Vec<_> = output.ticks.iter().map(|tick| inputs(tick)).collect();
+    assert_eq!(
+        ticks,
+        [
+            [(0, 1.0, 1.0, false), (1, 1.0, 1.0, false)],
+            [(0, 1.0, 1.0, true), (1, 1.0, 1.0, true)],
+            [(0, 2.0, 1.0, true), (1, 2.0, 1.0, true)],
+        ]
+    );
+}
+
+/// Reports how many ball predictions it got as throttle for each of its cars,
+/// and whether the tick has a ball prediction as jump
+struct CountingHivemind {
+    indices: Vec<u32>,
+    ball_predictions: u32,
+}
+
+impl Hivemind for CountingHivemind {
+    fn new(context: HivemindContext, _packet_queue: &mut PacketQueue) -> Self {
+        Self {
+            indices: context
+                .controllable_team_info
+                .controllables
+                .iter()
+                .map(|controllable| controllable.index)
+                .collect(),
+            ball_predictions: 0,
+        }
+    }
+
+    fn tick(&mut self, game_packet: TickPacket<'_>, packet_queue: &mut PacketQueue) {
+        for &player_index in &self.indices {
+            packet_queue.push(PlayerInput {
+                player_index,
+                controller_state: ControllerState {
+                    throttle: self.ball_predictions as f32,
+                    jump: game_packet.ball_prediction().is_some(),
+                    ..Default::default()
+                },
+            });
+        }
+    }
+
+    fn on_ball_prediction(&mut self, _ball_prediction: &BallPrediction) {
+        self.ball_predictions += 1;
+    }
+}
+
+#[test]
+fn replays_recorded_hivemind() {
+    // like the runners, only the latest ball prediction before a game packet is passed on
+    let session = record_session(
+        Some("rlbot/replay-test"),
+        vec![
+            GamePacket::default().into(),
+            BallPrediction::default().into(),
+            BallPrediction::default().into(),
+            GamePacket::default().into(),
+        ],
+    );
+    let output =
+        replay_hivemind::<CountingHivemind>(SessionReader::new(session.as_slice()).unwrap())
+            .unwrap();
+
+    let ticks: Vec<_> = output.ticks.iter().map(|tick| inputs(tick)).collect();
+    assert_eq!(
+        ticks,
+        [
+            [(0, 0.0, 0.0, false), (1, 0.0, 0.0, false)],
+            [(0, 1.0, 0.0, true), (1, 1.0, 0.0, true)],
+        ]
+    );
+}
+
+#[test]
+fn replay_needs_starting_info() {
+    let mut session = Vec::new();
+    drop(Recorder::new(MemoryStream::pair().0, &mut session).unwrap());
+
+    assert!(replay_script::<EchoScript>(SessionReader::new(session.as_slice()).unwrap()).is_err());
+}
@@ -0,0 +1,86 @@
+use rlbot::{
+    RLBotConnection, RLBotError,
+    flat::{RenderGroup, RenderMessage, RenderType, String2D},
+    testing::MockServer,
+    transport::MemoryStream,
+};
+
+fn text(text: String) -> RenderMessage {
+    RenderMessage {
+        variety: RenderType::String2D(Box::new(String2D {
+            text,
+            ..Default::default()
+        })),
+        color: Default::default(),
+    }
+}
+
+/// A group that's roughly three times too big for one packet
+fn oversized_group() -> RenderGroup {
+    RenderGroup {
+        render_messages: (0..48)
+            .map(|i| text(format!("{i:02}{}", "x".repeat(4000))))
+            .collect(),
+        id: 7,
+    }
+}
+
+#[test]
+fn oversized_group_is_split() {
+    let (bot_side, server_side) = MemoryStream::pair();
+    let mut bot = RLBotConnection::from_transport(bot_side);
+    let mut server = MockServer::from_transport(server_side);
+
+    assert!(matches!(
+        bot.send_packet(oversized_group()),
+        Err(RLBotError::PayloadTooLarge { .. })
+    ));
+
+    bot.set_split_render_groups(Some(100..110));
+    // the extra ids start over with every send
+    bot.send_packet(oversized_group()).unwrap();
+    bot.send_packet(oversized_group()).unwrap();
+    drop(bot);
+    while server.recv().is_ok() {}
+
+    let groups: Vec<_> = server.render_groups().collect();
+    let (first, second) = groups.split_at(groups.len() / 2);
+    assert!(first.len() > 1);
+    assert_eq!(first, second);
+
+    let ids: Vec<_> = first.iter().map(|group| group.id).collect();
+    let expected: Vec<_> = [7].into_iter().chain(100..).take(first.len()).collect();
+    assert_eq!(ids, expected);
+
+    let messages: Vec<_> = first
+        .iter()
+        .flat_map(|group| group.render_messages.iter().cloned())
+        .collect();
+    assert_eq!(messages, oversized_group().render_messages);
+}
+
+#[test]
+fn split_needs_enough_extra_ids() {
+    let mut bot = RLBotConnection::from_transport(MemoryStream::pair().0);
+
+    bot.set_split_render_groups(Some(100..101));
+    assert!(matches!(
+        bot.send_packet(oversized_group()),
+        Err(RLBotError::PayloadTooLarge { .. })
+    ));
+}
+
+#[test]
+fn single_message_too_large() {
+    let mut bot = RLBotConnection::from_transport(MemoryStream::pair().0);
+
+    bot.set_split_render_groups(Some(100..110));
+    let group = RenderGroup {
+        render_messages: vec![text("x".repeat(70_000))],
+        id: 7,
+    };
+    assert!(matches!(
+        bot.send_packet(group),
+        Err(RLBotError::PayloadTooLarge { data_type: 7, .. })
+    ));
+}
@@ -0,0 +1,74 @@
+use std::thread;
+
+use rlbot::{
+    ClientPacket, RLBotConnection, ServerPacket,
+    flat::{MatchComm, StopCommand},
+    testing::MockServer,
+    transport::MemoryStream,
+};
+
+fn match_comm(content: u8) -> MatchComm {
+    MatchComm {
+        index: 0,
+        team: 0,
+        team_only: false,
+        display: None,
+        content: vec![content],
+    }
+}
+
+#[test]
+fn halves_work_on_different_threads() {
+    let (bot_side, server_side) = MemoryStream::pair();
+    let mut server = MockServer::from_transport(server_side);
+
+    server.send(match_comm(1)).unwrap();
+    server.send(match_comm(2)).unwrap();
+
+    let mut bot = RLBotConnection::from_transport(bot_side);
+    // buffered before splitting
+    assert!(matches!(bot.recv_packet().unwrap(), ServerPacket::MatchComm(x) if x.content == [1]));
+
+    let (mut reader, mut writer) = bot.split().unwrap();
+
+    let receiving = thread::spawn(move || {
+        let mut received = Vec::new();
+        loop {
+            match reader.recv_packet().unwrap() {
+                ServerPacket::MatchComm(x) => received.push(x.content[0]),
+                ServerPacket::None => break,
+                packet => panic!("unexpected packet: {packet:?}"),
+            }
+        }
+        (reader, received)
+    });
+
+    writer
+        .send_packet(StopCommand {
+            shutdown_server: false,
+        })
+        .unwrap();
+    assert!(matches!(
+        server.recv().unwrap(),
+        ClientPacket::StopCommand(_)
+    ));
+
+    server.send(match_comm(3)).unwrap();
+    server.send(ServerPacket::None).unwrap();
+
+    let (reader, received) = receiving.join().unwrap();
+    assert_eq!(received, [2, 3]);
+
+    let mut bot = reader.reunite(writer).unwrap();
+    bot.send_packet(ClientPacket::InitComplete).unwrap();
+    assert!(matches!(server.recv().unwrap(), ClientPacket::InitComplete));
+}
+
+#[test]
+fn reunite_checks_halves() {
+    let (a, b) = MemoryStream::pair();
+    let (reader_a, _) = RLBotConnection::from_transport(a).split().unwrap();
+    let (_, writer_b) = RLBotConnection::from_transport(b).split().unwrap();
+
+    assert!(reader_a.reunite(writer_b).is_err());
+}
@@ -0,0 +1,108 @@
+use std::io::{self, Read, Write};
+
+use rlbot::{
+    RLBotConnection, ServerPacket,
+    flat::MatchComm,
+    testing::MockServer,
+    transport::{MemoryStream, Transport},
+};
+
+fn match_comm(content: u8) -> MatchComm {
+    MatchComm {
+        index: 0,
+        team: 0,
+        team_only: false,
+        display: None,
+        content: vec![content],
+    }
+}
+
+/// A transport that only supports blocking reads
+struct BlockingOnly(MemoryStream);
+
+impl Read for BlockingOnly {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for BlockingOnly {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Transport for BlockingOnly {}
+
+#[test]
+fn blocking_only_transport_returns_buffered_packets() {
+    let (bot_side, server_side) = MemoryStream::pair();
+
+    let mut bot = RLBotConnection::from_transport(BlockingOnly(bot_side));
+    let mut server = MockServer::from_transport(server_side);
+
+    server.send(match_comm(1)).unwrap();
+    server.send(match_comm(2)).unwrap();
+
+    // blocking reads buffer everything that's available, so the second comm comes along
+    let ServerPacket::MatchComm(first) = bot.recv_packet().unwrap() else {
+        panic!("expected a match comm");
+    };
+    assert_eq!(first.content, [1]);
+
+    let Some(ServerPacket::MatchComm(second)) = bot.try_recv_packet().unwrap() else {
+        panic!("expected a buffered match comm");
+    };
+    assert_eq!(second.content, [2]);
+
+    // nothing is buffered anymore, and reading would block
+    assert!(bot.try_recv_packet().unwrap().is_none());
+
+    bot.send_packet(match_comm(3)).unwrap();
+    assert!(server.recv().is_ok());
+}
+
+/// The bytes the server sends for `packet`
+fn frame(packet: impl Into<ServerPacket>) -> Vec<u8> {
+    let (mut raw, server_side) = MemoryStream::pair();
+    MockServer::from_transport(server_side)
+        .send(packet)
+        .unwrap();
+
+    let mut frame = Vec::new();
+    raw.read_to_end(&mut frame).unwrap();
+    frame
+}
+
+#[test]
+fn partial_frames_are_completed_later() {
+    let frame = frame(MatchComm {
+        display: Some("partial".into()),
+        ..match_comm(7)
+    });
+    // the header is 4 bytes, and the payload is split in two
+    let middle = 4 + (frame.len() - 4) / 2;
+    assert!(middle > 4);
+
+    let (bot_side, mut raw) = MemoryStream::pair();
+    let mut bot = RLBotConnection::from_transport(bot_side);
+
+    raw.write_all(&frame[..4]).unwrap();
+    assert!(bot.try_recv_packet().unwrap().is_none());
+
+    raw.write_all(&frame[4..middle]).unwrap();
+    assert!(bot.try_recv_packet().unwrap().is_none());
+
+    raw.write_all(&frame[middle..]).unwrap();
+    let Some(ServerPacket::MatchComm(match_comm)) = bot.try_recv_packet().unwrap() else {
+        panic!("expected the completed match comm");
+    };
+    assert_eq!(match_comm.content, [7]);
+    assert_eq!(match_comm.display.as_deref(), Some("partial"));
+
+    assert!(bot.try_recv_packet().unwrap().is_none());
+}